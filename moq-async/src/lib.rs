@@ -2,8 +2,10 @@ mod close;
 mod futures;
 mod lock;
 mod spawn;
+mod time;
 
 pub use close::*;
 pub use futures::*;
 pub use lock::*;
 pub use spawn::*;
+pub use time::*;
@@ -19,7 +19,7 @@ impl<T> Lock<T> {
 		}
 	}
 
-	pub fn lock(&self) -> LockGuard<T> {
+	pub fn lock(&self) -> LockGuard<'_, T> {
 		LockGuard {
 			inner: self.inner.lock().unwrap(),
 		}
@@ -34,7 +34,7 @@ impl<T> Lock<T> {
 		}
 	}
 
-	pub fn lock(&self) -> LockGuard<T> {
+	pub fn lock(&self) -> LockGuard<'_, T> {
 		LockGuard {
 			inner: self.inner.borrow_mut(),
 		}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+// Like spawn, we have per-platform implementations of sleep and now.
+// Tokio's timer requires a runtime, which we don't have on WASM.
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub async fn sleep(duration: Duration) {
+	tokio::time::sleep(duration).await;
+}
+
+// Uses tokio's clock so it can be paused and advanced in tests.
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub fn now() -> std::time::Instant {
+	tokio::time::Instant::now().into_std()
+}
+
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+pub fn now() -> web_time::Instant {
+	web_time::Instant::now()
+}
+
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+pub async fn sleep(duration: Duration) {
+	let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+		let global = js_sys::global();
+		let timeout = js_sys::Reflect::get(&global, &"setTimeout".into()).expect("missing setTimeout");
+		let timeout = js_sys::Function::from(timeout);
+
+		let millis = duration.as_millis().min(i32::MAX as u128) as f64;
+		timeout
+			.call2(&global, &resolve, &millis.into())
+			.expect("failed to call setTimeout");
+	});
+
+	// The promise never rejects.
+	wasm_bindgen_futures::JsFuture::from(promise).await.ok();
+}
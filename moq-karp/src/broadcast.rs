@@ -29,6 +29,7 @@ impl BroadcastProducer {
 			path: full,
 			priority: -1,
 			order: moq_transfork::GroupOrder::Desc,
+			..Default::default()
 		}
 		.produce();
 
@@ -58,6 +59,7 @@ impl BroadcastProducer {
 			priority: info.track.priority,
 			// TODO add these to the catalog and support higher latencies.
 			order: moq_transfork::GroupOrder::Desc,
+			..Default::default()
 		}
 		.produce();
 
@@ -91,6 +93,7 @@ impl BroadcastProducer {
 			priority: info.track.priority,
 			// TODO add these to the catalog and support higher latencies.
 			order: moq_transfork::GroupOrder::Desc,
+			..Default::default()
 		}
 		.produce();
 
@@ -201,7 +204,7 @@ impl BroadcastConsumer {
 				Some(announced) = self.announced.next() => {
 					// Load or unload based on the announcement.
 					match announced {
-						Announced::Active(suffix) => self.load(suffix),
+						Announced::Active(suffix, _) => self.load(suffix),
 						Announced::Ended(suffix) => self.unload(suffix),
 						Announced::Live => {
 							// Return None if we're caught up to live with no broadcast.
@@ -248,6 +251,7 @@ impl BroadcastConsumer {
 			path,
 			priority: -1,
 			order: moq_transfork::GroupOrder::Desc,
+			..Default::default()
 		};
 
 		self.catalog_track = Some(self.session.subscribe(track));
@@ -278,6 +282,7 @@ impl BroadcastConsumer {
 
 			// TODO add these to the catalog and support higher latencies.
 			order: moq_transfork::GroupOrder::Desc,
+			..Default::default()
 		};
 
 		let track = self.session.subscribe(track);
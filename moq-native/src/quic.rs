@@ -98,11 +98,14 @@ impl Endpoint {
 
 pub struct Server {
 	quic: quinn::Endpoint,
-	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<web_transport_quinn::Session>>>,
+	accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<(Url, web_transport_quinn::Session)>>>,
 }
 
 impl Server {
-	pub async fn accept(&mut self) -> Option<web_transport_quinn::Session> {
+	/// Accept the next session, along with the URL requested by the client.
+	///
+	/// Raw QUIC connections don't have a URL, so one is made from the SNI: `moqf://host`
+	pub async fn accept(&mut self) -> Option<(Url, web_transport_quinn::Session)> {
 		loop {
 			tokio::select! {
 				res = self.quic.accept() => {
@@ -110,15 +113,15 @@ impl Server {
 					self.accept.push(Self::accept_session(conn).boxed());
 				}
 				Some(res) = self.accept.next() => {
-					if let Ok(session) = res {
-						return Some(session)
+					if let Ok(accepted) = res {
+						return Some(accepted)
 					}
 				}
 			}
 		}
 	}
 
-	async fn accept_session(conn: quinn::Incoming) -> anyhow::Result<web_transport_quinn::Session> {
+	async fn accept_session(conn: quinn::Incoming) -> anyhow::Result<(Url, web_transport_quinn::Session)> {
 		let mut conn = conn.accept()?;
 
 		let handshake = conn
@@ -139,25 +142,32 @@ impl Server {
 		let span = tracing::Span::current();
 		span.record("id", conn.stable_id()); // TODO can we get this earlier?
 
-		let session = match alpn.as_bytes() {
+		let accepted = match alpn.as_bytes() {
 			web_transport::quinn::ALPN => {
 				// Wait for the CONNECT request.
 				let request = web_transport::quinn::Request::accept(conn)
 					.await
 					.context("failed to receive WebTransport request")?;
 
+				let url = request.url().clone();
+
 				// Accept the CONNECT request.
-				request
+				let session = request
 					.ok()
 					.await
-					.context("failed to respond to WebTransport request")?
+					.context("failed to respond to WebTransport request")?;
+
+				(url, session)
 			}
 			// A bit of a hack to pretend like we're a WebTransport session
-			moq_transfork::ALPN => conn.into(),
+			moq_transfork::ALPN => {
+				let url = Url::parse(&format!("moqf://{}", host)).context("invalid server name")?;
+				(url, conn.into())
+			}
 			_ => anyhow::bail!("unsupported ALPN: {}", alpn),
 		};
 
-		Ok(session)
+		Ok(accepted)
 	}
 
 	pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
@@ -0,0 +1,314 @@
+use std::{fs, path::PathBuf, time};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use clap::Parser;
+use moq_transfork::{message, Path};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Clone, Parser)]
+pub struct AuthConfig {
+	/// Require clients to provide a JWT signed with one of the HMAC-SHA256 keys in this file.
+	/// Each non-empty line is a key; the first is used to sign tokens for other cluster nodes.
+	///
+	/// Clients provide the token via the `jwt` query parameter, or the token setup extension for raw QUIC.
+	/// If not provided, then authentication is disabled.
+	#[arg(long)]
+	pub auth_key: Option<PathBuf>,
+}
+
+impl AuthConfig {
+	pub fn load(&self) -> anyhow::Result<Option<Auth>> {
+		let path = match &self.auth_key {
+			Some(path) => path,
+			None => return Ok(None),
+		};
+
+		let contents = fs::read_to_string(path).context("failed to read auth key file")?;
+		Auth::new(contents.lines()).map(Some)
+	}
+}
+
+/// The claims contained in a token.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Claims {
+	/// Path prefixes the client may announce, separated by `/`. An empty string allows any path.
+	#[serde(default)]
+	pub publish: Vec<String>,
+
+	/// Path prefixes the client may subscribe to, separated by `/`. An empty string allows any path.
+	#[serde(default)]
+	pub subscribe: Vec<String>,
+
+	/// The expiration time in seconds since the UNIX epoch.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub exp: Option<u64>,
+}
+
+// Cluster tokens are signed for each connection, so they only need to be valid long enough to connect.
+const CLUSTER_TOKEN_TTL: time::Duration = time::Duration::from_secs(60);
+
+impl Claims {
+	// Allow everything for a short time, used to connect to other cluster nodes.
+	pub fn cluster() -> Self {
+		let exp = time::SystemTime::now()
+			.duration_since(time::UNIX_EPOCH)
+			.map(|now| (now + CLUSTER_TOKEN_TTL).as_secs())
+			.ok();
+
+		Self {
+			publish: vec!["".to_string()],
+			subscribe: vec!["".to_string()],
+			exp,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+	alg: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	typ: Option<String>,
+}
+
+// Verifies HS256 JSON Web Tokens.
+#[derive(Clone)]
+pub struct Auth {
+	keys: Vec<hmac::Key>,
+}
+
+impl Auth {
+	pub fn new<I, K>(keys: I) -> anyhow::Result<Self>
+	where
+		I: IntoIterator<Item = K>,
+		K: AsRef<str>,
+	{
+		let keys: Vec<_> = keys
+			.into_iter()
+			.filter_map(|key| {
+				let key = key.as_ref().trim();
+				(!key.is_empty()).then(|| hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes()))
+			})
+			.collect();
+
+		anyhow::ensure!(!keys.is_empty(), "no auth keys provided");
+
+		Ok(Self { keys })
+	}
+
+	/// Sign the claims with the first key.
+	pub fn sign(&self, claims: &Claims) -> String {
+		let header = Header {
+			alg: "HS256".to_string(),
+			typ: Some("JWT".to_string()),
+		};
+
+		let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap());
+		let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap());
+		let message = format!("{}.{}", header, claims);
+
+		let signature = hmac::sign(&self.keys[0], message.as_bytes());
+		format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+	}
+
+	/// Verify the token and return the permissions it grants.
+	pub fn verify(&self, token: &str) -> anyhow::Result<Permissions> {
+		let mut parts = token.split('.');
+		let (header, claims, signature) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+			(Some(header), Some(claims), Some(signature), None) => (header, claims, signature),
+			_ => anyhow::bail!("malformed token"),
+		};
+
+		let decoded = URL_SAFE_NO_PAD.decode(header).context("invalid header encoding")?;
+		let decoded: Header = serde_json::from_slice(&decoded).context("invalid header")?;
+		anyhow::ensure!(decoded.alg == "HS256", "unsupported algorithm: {}", decoded.alg);
+
+		let signature = URL_SAFE_NO_PAD
+			.decode(signature)
+			.context("invalid signature encoding")?;
+
+		// Everything before the last dot is signed.
+		let message = &token[..header.len() + claims.len() + 1];
+		let valid = self
+			.keys
+			.iter()
+			.any(|key| hmac::verify(key, message.as_bytes(), &signature).is_ok());
+		anyhow::ensure!(valid, "invalid signature");
+
+		let claims = URL_SAFE_NO_PAD.decode(claims).context("invalid claims encoding")?;
+		let claims: Claims = serde_json::from_slice(&claims).context("invalid claims")?;
+
+		if let Some(exp) = claims.exp {
+			let now = time::SystemTime::now()
+				.duration_since(time::UNIX_EPOCH)
+				.context("invalid system time")?;
+			anyhow::ensure!(now.as_secs() < exp, "token expired");
+		}
+
+		Ok(Permissions::new(&claims))
+	}
+
+	/// Verify the token provided via the `jwt` query parameter.
+	pub fn verify_url(&self, url: &Url) -> anyhow::Result<Permissions> {
+		let token = url
+			.query_pairs()
+			.find(|(key, _)| key == "jwt")
+			.map(|(_, value)| value)
+			.context("missing token")?;
+
+		self.verify(&token)
+	}
+
+	/// Verify the token provided via the URL, or otherwise the [message::Token] setup extension.
+	///
+	/// Raw QUIC connections don't have a query string, so they can only use the extension.
+	pub fn verify_session(&self, url: &Url, extensions: &message::Extensions) -> anyhow::Result<Permissions> {
+		if url.query_pairs().any(|(key, _)| key == "jwt") {
+			return self.verify_url(url);
+		}
+
+		let token = extensions
+			.get::<message::Token>()
+			.context("invalid token extension")?
+			.context("missing token")?;
+
+		self.verify(&token.0)
+	}
+}
+
+/// The path prefixes a client is allowed to access.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Permissions {
+	publish: Vec<Path>,
+	subscribe: Vec<Path>,
+}
+
+impl Permissions {
+	pub fn new(claims: &Claims) -> Self {
+		Self {
+			publish: claims.publish.iter().map(|prefix| parse_path(prefix)).collect(),
+			subscribe: claims.subscribe.iter().map(|prefix| parse_path(prefix)).collect(),
+		}
+	}
+
+	pub fn can_publish(&self, path: &Path) -> bool {
+		self.publish.iter().any(|prefix| path.has_prefix(prefix))
+	}
+
+	pub fn can_subscribe(&self, path: &Path) -> bool {
+		self.subscribe.iter().any(|prefix| path.has_prefix(prefix))
+	}
+}
+
+// Split a path on `/`, ignoring empty parts so `a/b/` and `/a/b` are equivalent.
+fn parse_path(path: &str) -> Path {
+	path.split('/')
+		.filter(|part| !part.is_empty())
+		.fold(Path::default(), |path, part| path.push(part))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn verify() {
+		let auth = Auth::new(["first", "", "second"]).unwrap();
+		let claims = Claims {
+			publish: vec!["room/alice".to_string()],
+			subscribe: vec!["room".to_string()],
+			exp: None,
+		};
+
+		let token = auth.sign(&claims);
+		let permissions = auth.verify(&token).unwrap();
+		assert_eq!(permissions, Permissions::new(&claims));
+
+		let alice = Path::default().push("room").push("alice").push("video");
+		let bob = Path::default().push("room").push("bob");
+
+		assert!(permissions.can_publish(&alice));
+		assert!(!permissions.can_publish(&bob));
+		assert!(permissions.can_subscribe(&bob));
+		assert!(!permissions.can_subscribe(&Path::default().push("other")));
+
+		// Any of the keys can be used to verify, for rotation.
+		let rotated = Auth::new(["second"]).unwrap().sign(&claims);
+		assert!(auth.verify(&rotated).is_ok());
+
+		// But not an unknown key.
+		let unknown = Auth::new(["unknown"]).unwrap().sign(&claims);
+		assert!(auth.verify(&unknown).is_err());
+
+		// Tampering with the claims invalidates the signature.
+		let forged = Auth::new(["unknown"]).unwrap().sign(&Claims::cluster());
+		let forged = format!(
+			"{}.{}",
+			forged.rsplit_once('.').unwrap().0,
+			token.rsplit_once('.').unwrap().1
+		);
+		assert!(auth.verify(&forged).is_err());
+
+		assert!(auth.verify("garbage").is_err());
+	}
+
+	#[test]
+	fn expired() {
+		let auth = Auth::new(["key"]).unwrap();
+
+		let expired = auth.sign(&Claims {
+			exp: Some(1),
+			..Claims::cluster()
+		});
+		assert!(auth.verify(&expired).is_err());
+
+		let valid = auth.sign(&Claims {
+			exp: Some(u64::MAX),
+			..Claims::cluster()
+		});
+		assert!(auth.verify(&valid).is_ok());
+	}
+
+	#[test]
+	fn url() {
+		let auth = Auth::new(["key"]).unwrap();
+		let token = auth.sign(&Claims::cluster());
+
+		let url = Url::parse(&format!("https://relay.example.com/?jwt={}", token)).unwrap();
+		let permissions = auth.verify_url(&url).unwrap();
+		assert!(permissions.can_publish(&Path::default().push("anything")));
+
+		let url = Url::parse("https://relay.example.com/").unwrap();
+		assert!(auth.verify_url(&url).is_err());
+	}
+
+	#[test]
+	fn extension() {
+		let auth = Auth::new(["key"]).unwrap();
+		let url = Url::parse("moqf://relay.example.com").unwrap();
+
+		let mut extensions = message::Extensions::default();
+		assert!(auth.verify_session(&url, &extensions).is_err());
+
+		extensions.set(message::Token(auth.sign(&Claims::cluster())));
+		let permissions = auth.verify_session(&url, &extensions).unwrap();
+		assert!(permissions.can_subscribe(&Path::default().push("anything")));
+
+		// The URL takes precedence if it contains a token.
+		let url = Url::parse("https://relay.example.com/?jwt=garbage").unwrap();
+		assert!(auth.verify_session(&url, &extensions).is_err());
+	}
+
+	#[test]
+	fn cluster() {
+		let auth = Auth::new(["key"]).unwrap();
+
+		// Cluster tokens expire shortly after they're signed.
+		let claims = Claims::cluster();
+		let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap();
+		assert!(claims.exp.unwrap() <= (now + CLUSTER_TOKEN_TTL).as_secs());
+		assert!(auth.verify(&auth.sign(&claims)).is_ok());
+	}
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+// Exponential backoff with jitter, used to reconnect to remote nodes.
+//
+// The jitter avoids every node reconnecting at the same time after an outage.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+	min: Duration,
+	max: Duration,
+
+	// The delay before jitter is applied.
+	current: Duration,
+}
+
+impl Default for Backoff {
+	fn default() -> Self {
+		Self::new(Duration::from_millis(500), Duration::from_secs(30))
+	}
+}
+
+impl Backoff {
+	pub fn new(min: Duration, max: Duration) -> Self {
+		Self { min, max, current: min }
+	}
+
+	// Returns how long to wait before the next attempt, doubling the delay for next time.
+	//
+	// The result is randomly chosen between half and all of the current delay.
+	pub fn delay(&mut self) -> Duration {
+		let delay = self.current;
+		self.current = (self.current * 2).min(self.max);
+
+		let half = delay / 2;
+		half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+	}
+
+	// Start over with the minimum delay, called after a successful connection.
+	pub fn reset(&mut self) {
+		self.current = self.min;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn backoff() {
+		let min = Duration::from_millis(100);
+		let max = Duration::from_millis(1000);
+		let mut backoff = Backoff::new(min, max);
+
+		for expected in [100, 200, 400, 800, 1000, 1000] {
+			let expected = Duration::from_millis(expected);
+			let delay = backoff.delay();
+			assert!(delay >= expected / 2 && delay <= expected, "{:?} {:?}", delay, expected);
+		}
+
+		backoff.reset();
+		assert!(backoff.delay() <= min);
+	}
+}
@@ -1,13 +1,22 @@
-use std::collections::HashMap;
+use std::{
+	collections::{HashMap, HashSet},
+	fmt,
+	sync::{Arc, Mutex},
+};
 
 use anyhow::Context;
+use bytes::Bytes;
 use clap::Parser;
+use futures::FutureExt;
 use moq_native::quic;
-use moq_transfork::{Announced, AnnouncedProducer, Error, Path, Router, RouterConsumer, RouterProducer};
+use moq_transfork::{
+	Announced, AnnouncedConsumer, AnnouncedProducer, Error, Path, Router, RouterConsumer, RouterProducer, Session,
+	Track, TrackCache, TrackLimits, TrackProducer,
+};
 use tracing::Instrument;
 use url::Url;
 
-use crate::Origins;
+use crate::{Auth, Backoff, Claims, LimitsConfig, Metrics, Origins};
 
 #[derive(Clone, Parser)]
 pub struct ClusterConfig {
@@ -26,6 +35,33 @@ pub struct ClusterConfig {
 	pub cluster_node: Option<String>,
 }
 
+/// The health of the connection to a remote node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoteState {
+	/// Attempting to establish a session.
+	Connecting,
+
+	/// The session is established and announcing tracks.
+	Connected,
+
+	/// The last attempt failed, waiting before trying again.
+	Failing,
+}
+
+impl RemoteState {
+	pub const ALL: [RemoteState; 3] = [Self::Connecting, Self::Connected, Self::Failing];
+}
+
+impl fmt::Display for RemoteState {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Connecting => write!(f, "connecting"),
+			Self::Connected => write!(f, "connected"),
+			Self::Failing => write!(f, "failing"),
+		}
+	}
+}
+
 #[derive(Clone)]
 pub struct Cluster {
 	config: ClusterConfig,
@@ -37,12 +73,33 @@ pub struct Cluster {
 	// Tracks announced by remote servers (cluster).
 	pub remotes: Origins,
 
+	// The tracks announced by either origin above, without duplicates.
+	combined: AnnouncedProducer,
+
 	// Used to route incoming requests to the origins above.
 	pub router: RouterConsumer,
+
+	// Counters exported via the web server.
+	pub metrics: Metrics,
+
+	// Used to verify client tokens, if authentication is enabled.
+	pub auth: Option<Auth>,
+
+	// The cache and memory limits applied to every relayed track.
+	pub cache: TrackCache,
+	pub limits: TrackLimits,
+
+	// The relayed tracks, shared by every downstream subscriber.
+	upstreams: Arc<Mutex<HashMap<Path, TrackProducer>>>,
 }
 
 impl Cluster {
-	pub fn new(config: ClusterConfig, client: quic::Client) -> Self {
+	pub fn new(
+		config: ClusterConfig,
+		client: quic::Client,
+		auth: Option<Auth>,
+		limits: &LimitsConfig,
+	) -> anyhow::Result<Self> {
 		let (producer, consumer) = Router { capacity: 1024 }.produce();
 
 		let this = Cluster {
@@ -51,28 +108,143 @@ impl Cluster {
 			router: consumer,
 			locals: Origins::new(),
 			remotes: Origins::new(),
+			combined: AnnouncedProducer::new(),
+			metrics: Metrics::new(),
+			auth,
+			cache: limits.cache()?,
+			limits: limits.load(),
+			upstreams: Default::default(),
 		};
 
 		tokio::spawn(this.clone().run_router(producer).in_current_span());
+		tokio::spawn(this.clone().run_combined().in_current_span());
+
+		Ok(this)
+	}
+
+	// Returns the session that should serve the path, if any.
+	//
+	// Local origins are preferred over remote origins, as they're one less hop away.
+	// Within each, the most recent announcement wins.
+	// When the chosen session closes, its announcements end and the next origin is used instead.
+	pub fn route(&self, path: &Path) -> Option<Session> {
+		route(&self.origins(), path)
+	}
+
+	// The origins in order of preference.
+	fn origins(&self) -> [Origins; 2] {
+		[self.locals.clone(), self.remotes.clone()]
+	}
+
+	// Subscribe to the tracks announced by either local or remote origins, matching a prefix.
+	pub fn announced_prefix(&self, prefix: Path) -> AnnouncedConsumer {
+		self.combined.subscribe_prefix(prefix)
+	}
+
+	// Combine the local and remote announcements, so a path announced by both isn't unannounced until both end.
+	async fn run_combined(mut self) {
+		let mut locals = self.locals.announced();
+		let mut remotes = self.remotes.announced();
+
+		// The metadata for each path announced by (local, remote) origins.
+		let mut active: HashMap<Path, (Option<Bytes>, Option<Bytes>)> = HashMap::new();
+
+		loop {
+			let (announced, local) = tokio::select! {
+				Some(announced) = locals.next() => (announced, true),
+				Some(announced) = remotes.next() => (announced, false),
+				else => return,
+			};
 
-		this
+			match announced {
+				Announced::Active(path, metadata) => {
+					let entry = active.entry(path.clone()).or_default();
+					let conflict = match local {
+						true => entry.0.replace(metadata).is_none() && entry.1.is_some(),
+						false => entry.1.replace(metadata).is_none() && entry.0.is_some(),
+					};
+
+					if conflict {
+						tracing::warn!(?path, "conflicting local and remote origin, preferring local");
+					}
+
+					// Advertise the metadata of the preferred origin.
+					let metadata = entry.0.clone().or_else(|| entry.1.clone()).unwrap();
+					self.combined.announce_with(path, metadata);
+				}
+				Announced::Ended(path) => {
+					if let Some(entry) = active.get_mut(&path) {
+						match local {
+							true => entry.0 = None,
+							false => entry.1 = None,
+						}
+
+						match entry.0.clone().or_else(|| entry.1.clone()) {
+							Some(metadata) => {
+								self.combined.announce_with(path, metadata);
+							}
+							None => {
+								active.remove(&path);
+								self.combined.unannounce(&path);
+							}
+						}
+					}
+				}
+				Announced::Live => {
+					// Ignore.
+				}
+			}
+		}
 	}
 
 	// This is the GUTS of the entire relay.
 	// We route any incoming track requests to the appropriate session.
 	async fn run_router(self, mut router: RouterProducer) {
 		while let Some(req) = router.requested().await {
-			let origin = if let Some(origin) = self.locals.route(&req.track.path).clone() {
-				origin
-			} else if let Some(origin) = self.remotes.route(&req.track.path).clone() {
-				origin
-			} else {
-				req.close(Error::NotFound);
+			self.metrics.router_request();
+
+			let path = req.track.path.clone();
+
+			if let Some(upstream) = self.upstreams.lock().unwrap().get(&path) {
+				req.serve(upstream.subscribe());
 				continue;
+			}
+
+			let origin = match self.route(&path) {
+				Some(origin) => origin,
+				None => {
+					self.metrics.router_failure();
+					req.close(Error::NotFound);
+					continue;
+				}
 			};
 
-			let track = origin.subscribe(req.track.clone());
-			req.serve(track)
+			// The upstream subscription is shared by every downstream subscriber, so only the path is requested.
+			let track = Track {
+				path: path.clone(),
+				cache: self.cache,
+				limits: self.limits.clone(),
+				..Default::default()
+			};
+
+			let (mut upstream, consumer) = track.produce();
+
+			// Wait for the origin's info instead of using our defaults.
+			upstream.clear_info();
+
+			self.upstreams.lock().unwrap().insert(path.clone(), upstream.clone());
+			req.serve(consumer);
+
+			let upstreams = self.upstreams.clone();
+			let origins = self.origins();
+
+			tokio::spawn(
+				async move {
+					run_upstream(&origins, origin, upstream).await;
+					upstreams.lock().unwrap().remove(&path);
+				}
+				.in_current_span(),
+			);
 		}
 	}
 
@@ -92,27 +264,12 @@ impl Cluster {
 			myself.announce(origin);
 		}
 
-		// If we're using a root node, then we have to connect to it.
-		let mut announced = match root.as_ref() {
-			Some(root) if Some(root) != node.as_ref() => {
-				tracing::info!(?root, "connecting to root");
-
-				// Connect to the root node.
-				let root = Url::parse(&format!("https://{}", root)).context("invalid root URL")?;
-				let root = self.client.connect(root).await.context("failed to connect to root")?;
-
-				let mut root = moq_transfork::Session::connect(root)
-					.await
-					.context("failed to establish root session")?;
-
-				// Announce ourselves as an origin to the root node.
-				root.announce(myself.subscribe());
-
-				tracing::info!(?origins, "waiting for prefix");
+		// Keep track of the active remotes.
+		let mut remotes = HashMap::new();
 
-				// Subscribe to available origins.
-				root.announced(origins.clone())
-			}
+		// If we're using a root node, then we have to connect to it.
+		let root = match root {
+			Some(root) if Some(&root) != node.as_ref() => root,
 			// Otherwise, we're the root node but we still want to connect to other nodes.
 			_ => {
 				// Announce ourselves as an origin to all connected clients.
@@ -127,26 +284,96 @@ impl Cluster {
 				tracing::info!(?node, "acting as root");
 
 				// Subscribe to the available origins.
-				self.locals.announced_prefix(origins.clone())
+				let announced = self.locals.announced_prefix(origins.clone());
+				return self.run_origins(announced, &mut remotes).await;
 			}
 		};
 
-		// Keep track of the active remotes.
-		let mut remotes = HashMap::new();
+		let mut backoff = Backoff::default();
+
+		// Reconnect to the root node with the same backoff used for remotes.
+		// The remotes keep running in the meantime, as they have their own connections.
+		loop {
+			let res = self
+				.run_root(&root, &origins, &myself, &mut remotes, &mut backoff)
+				.await;
+
+			let delay = backoff.delay();
+			match res {
+				Ok(()) => tracing::warn!(?delay, "root closed, reconnecting"),
+				Err(err) => tracing::error!(?err, ?delay, "root error, retrying"),
+			}
+
+			tokio::time::sleep(delay).await;
+		}
+	}
+
+	async fn run_root(
+		&self,
+		root: &str,
+		origins: &Path,
+		myself: &AnnouncedProducer,
+		remotes: &mut HashMap<String, tokio::task::JoinHandle<()>>,
+		backoff: &mut Backoff,
+	) -> anyhow::Result<()> {
+		tracing::info!(?root, "connecting to root");
+
+		// Connect to the root node.
+		let url = self.url(root).context("invalid root URL")?;
+		let conn = self.client.connect(url).await.context("failed to connect to root")?;
+
+		let mut session = moq_transfork::Session::connect(conn)
+			.await
+			.context("failed to establish root session")?;
+
+		// Only reset the backoff once the session is established.
+		backoff.reset();
+
+		// Announce ourselves as an origin to the root node.
+		session.announce(myself.subscribe())?;
+
+		tracing::info!(?origins, "waiting for prefix");
+
+		// Subscribe to available origins.
+		let announced = session.announced(origins.clone());
+
+		tokio::select! {
+			res = self.run_origins(announced, remotes) => res,
+			err = session.closed() => Err(err).context("root session closed"),
+		}
+	}
+
+	// Connect to each origin as it's discovered, and disconnect when it's no longer announced.
+	async fn run_origins(
+		&self,
+		mut announced: AnnouncedConsumer,
+		remotes: &mut HashMap<String, tokio::task::JoinHandle<()>>,
+	) -> anyhow::Result<()> {
+		let node = self.config.cluster_node.as_ref();
+
+		// The remotes announced by this session, so we can remove any that ended while we were reconnecting.
+		let mut seen = HashSet::new();
 
 		// Discover other origins.
 		// NOTE: The root node will connect to all other nodes as a client, ignoring the existing (server) connection.
 		// This ensures that nodes are advertising a valid hostname before any tracks get announced.
 		while let Some(announce) = announced.next().await {
 			match announce {
-				Announced::Active(path) => {
+				Announced::Active(path, _) => {
 					// Extract the hostname from the first part of the path.
 					let host = path.first().context("missing node")?.to_string();
-					if Some(&host) == node.as_ref() {
+					if Some(&host) == node {
 						// Skip ourselves.
 						continue;
 					}
 
+					seen.insert(host.clone());
+
+					if remotes.contains_key(&host) {
+						// Still connected from before the root reconnected.
+						continue;
+					}
+
 					tracing::info!(?host, "discovered origin");
 
 					let mut this = self.clone();
@@ -154,13 +381,21 @@ impl Cluster {
 
 					let handle = tokio::spawn(
 						async move {
+							let mut backoff = Backoff::default();
+
 							loop {
-								if let Err(err) = this.run_remote(&remote).await {
-									tracing::error!(?err, "remote error, retrying");
+								this.metrics.remote_state(&remote, RemoteState::Connecting);
+
+								let res = this.run_remote(&remote, &mut backoff).await;
+								this.metrics.remote_state(&remote, RemoteState::Failing);
+
+								let delay = backoff.delay();
+								match res {
+									Ok(()) => tracing::warn!(?delay, "remote closed, reconnecting"),
+									Err(err) => tracing::error!(?err, ?delay, "remote error, retrying"),
 								}
 
-								// TODO smarter backoff
-								tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+								tokio::time::sleep(delay).await;
 							}
 						}
 						.in_current_span(),
@@ -170,13 +405,15 @@ impl Cluster {
 				}
 				Announced::Ended(path) => {
 					let host = path.first().context("missing node")?.to_string();
-					if let Some(handle) = remotes.remove(&host) {
-						tracing::warn!(?host, "terminating remote");
-						handle.abort();
-					}
+					seen.remove(&host);
+					self.remove_remote(&host, remotes);
 				}
 				Announced::Live => {
-					// Ignore.
+					// Any remotes that weren't announced again are gone.
+					let stale: Vec<_> = remotes.keys().filter(|host| !seen.contains(*host)).cloned().collect();
+					for host in stale {
+						self.remove_remote(&host, remotes);
+					}
 				}
 			}
 		}
@@ -184,9 +421,31 @@ impl Cluster {
 		Ok(())
 	}
 
+	fn remove_remote(&self, host: &str, remotes: &mut HashMap<String, tokio::task::JoinHandle<()>>) {
+		if let Some(handle) = remotes.remove(host) {
+			tracing::warn!(?host, "terminating remote");
+			handle.abort();
+		}
+
+		self.metrics.remote_removed(host);
+	}
+
+	// Returns the URL for another node, including a token if authentication is enabled.
+	fn url(&self, host: &str) -> anyhow::Result<Url> {
+		let mut url = Url::parse(&format!("https://{}", host))?;
+
+		// Nodes share the same keys, so we can sign our own token.
+		if let Some(auth) = &self.auth {
+			let token = auth.sign(&Claims::cluster());
+			url.query_pairs_mut().append_pair("jwt", &token);
+		}
+
+		Ok(url)
+	}
+
 	#[tracing::instrument("remote", skip_all, err, fields(%host))]
-	async fn run_remote(&mut self, host: &str) -> anyhow::Result<()> {
-		let url = Url::parse(&format!("https://{}", host)).context("invalid node URL")?;
+	async fn run_remote(&mut self, host: &str, backoff: &mut Backoff) -> anyhow::Result<()> {
+		let url = self.url(host).context("invalid node URL")?;
 
 		// Connect to the remote node.
 		let conn = self.client.connect(url).await.context("failed to connect to remote")?;
@@ -195,16 +454,157 @@ impl Cluster {
 			.await
 			.context("failed to establish session")?;
 
-		session.route(self.router.clone());
+		// Only reset the backoff once the session is established.
+		backoff.reset();
+
+		self.metrics.remote_state(host, RemoteState::Connected);
+		let _metrics = self.metrics.session(session.clone());
+
+		session.route(self.router.clone())?;
 
 		// NOTE: We only announce local tracks to remote nodes.
 		// Otherwise there would be conflicts and we wouldn't know which node is the origin.
-		session.announce(self.locals.announced());
+		session.announce(self.locals.announced())?;
 
 		// Add any tracks to the list of remotes for routing.
 		let all = session.announced(Path::default());
-		self.remotes.announce(all, Some(session.clone())).await;
+		let mut remotes = self.remotes.clone();
 
-		Ok(())
+		// Remove the tracks as soon as the session closes, so requests fail fast instead of being routed to it.
+		tokio::select! {
+			_ = remotes.announce(all, Some(session.clone())) => Ok(()),
+			err = session.closed() => Err(err).context("session closed"),
+		}
+	}
+}
+
+// Returns the session that should serve the path, preferring the first origins with a route.
+fn route(origins: &[Origins], path: &Path) -> Option<Session> {
+	origins.iter().find_map(|origins| origins.route(path))
+}
+
+// Wait until the path is routed to a session other than the given one, or None if it's no longer routed.
+async fn reroute(origins: &[Origins], path: &Path, from: &Session) -> Option<Session> {
+	let mut changed: Vec<_> = origins.iter().map(Origins::changed).collect();
+
+	loop {
+		match route(origins, path) {
+			Some(session) if session == *from => (),
+			route => return route,
+		}
+
+		// Any route change is enough to check again.
+		// The senders live as long as the origins, so this never fails.
+		let changes = changed.iter_mut().map(|changed| Box::pin(changed.changed()));
+		let _ = futures::future::select_all(changes).await;
+	}
+}
+
+// Serve a relayed track from the origin, failing over to the next origin whenever the origin's session closes.
+// Consumers keep receiving groups from the same track, so they don't notice the failover.
+#[tracing::instrument("upstream", skip_all, fields(path = ?upstream.path))]
+async fn run_upstream(origins: &[Origins], mut origin: Session, upstream: TrackProducer) {
+	loop {
+		let err = match origin.subscribe_producer(upstream.clone()).await {
+			Ok(()) => return,
+			Err(err) => err,
+		};
+
+		// Any other error applies to the track itself, so there's nothing to fail over.
+		if origin.closed().now_or_never().is_none() {
+			upstream.close(err);
+			return;
+		}
+
+		let next = tokio::select! {
+			next = reroute(origins, &upstream.path, &origin) => next,
+			_ = upstream.unused() => return,
+		};
+
+		match next {
+			Some(next) => {
+				tracing::info!(?err, "origin closed, failing over");
+				origin = next;
+			}
+			None => {
+				upstream.close(err);
+				return;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use moq_transfork::loopback;
+
+	// Returns the relay's session to an origin, and the origin's session.
+	async fn origin() -> (Session, Session) {
+		let (client, server) = loopback::connect().await;
+		let (relay, origin) = tokio::join!(Session::connect(client), Session::accept(server));
+		(relay.unwrap(), origin.unwrap())
+	}
+
+	#[tokio::test]
+	async fn failover() {
+		let locals = Origins::new();
+		let mut announced = locals.announced();
+		let path = Path::default().push("room").push("alice");
+
+		let (relay_a, mut origin_a) = origin().await;
+		let (relay_b, mut origin_b) = origin().await;
+
+		let (mut track_a, reader) = Track::new(path.clone()).produce();
+		origin_a.publish(reader).unwrap();
+		let (mut track_b, reader) = Track::new(path.clone()).produce();
+		origin_b.publish(reader).unwrap();
+
+		// The most recent origin wins, so announce b first.
+		let mut a = AnnouncedProducer::new();
+		a.announce_with(path.clone(), Bytes::from_static(b"a"));
+		let mut b = AnnouncedProducer::new();
+		b.announce_with(path.clone(), Bytes::from_static(b"b"));
+
+		for (producer, session, metadata) in [(&b, &relay_b, "b"), (&a, &relay_a, "a")] {
+			let (mut this, consumer, origin) = (locals.clone(), producer.subscribe(), Some(session.clone()));
+			tokio::spawn(async move { this.announce(consumer, origin).await });
+
+			let expected = Announced::Active(path.clone(), Bytes::from(metadata));
+			assert_eq!(announced.next().await, Some(expected));
+		}
+
+		let origins = [locals.clone(), Origins::new()];
+		assert!(route(&origins, &path) == Some(relay_a.clone()));
+
+		let (mut upstream, mut consumer) = Track::new(path.clone()).produce();
+		upstream.clear_info();
+		tokio::spawn(async move { run_upstream(&origins, relay_a, upstream).await });
+		consumer.info().await.unwrap();
+
+		track_a.append_group().write_frame("a");
+		let mut group = consumer.next_group().await.unwrap().unwrap();
+		assert_eq!(group.sequence, 0);
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "a");
+
+		// The origin goes away, and with it the announcement.
+		origin_a.close(Error::Cancel);
+		drop(a);
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"b")))
+		);
+
+		// The same consumer keeps receiving groups from the next origin.
+		track_b.create_group(1).write_frame("b");
+		let mut group = consumer.next_group().await.unwrap().unwrap();
+		assert_eq!(group.sequence, 1);
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "b");
+
+		// The track ends once there are no origins left.
+		origin_b.close(Error::Cancel);
+		drop(b);
+		assert!(consumer.closed().await.is_err());
 	}
 }
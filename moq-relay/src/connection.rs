@@ -1,31 +1,238 @@
-use crate::Cluster;
+use moq_transfork::{Announced, AnnouncedConsumer, AnnouncedProducer, Error, Path, Router, RouterConsumer};
+use url::Url;
+
+use crate::{Cluster, Permissions};
 
 pub struct Connection {
 	id: u64,
 	session: web_transport::Session,
+	url: Url,
 	cluster: Cluster,
 }
 
 impl Connection {
-	pub fn new(id: u64, session: web_transport::Session, cluster: Cluster) -> Self {
-		Self { id, session, cluster }
+	pub fn new(id: u64, session: web_transport::Session, url: Url, cluster: Cluster) -> Self {
+		Self {
+			id,
+			session,
+			url,
+			cluster,
+		}
 	}
 
-	#[tracing::instrument("session", skip_all, err, fields(id = self.id))]
+	#[tracing::instrument("session", skip_all, err, fields(id = self.id, path = self.url.path()))]
 	pub async fn run(mut self) -> anyhow::Result<()> {
+		self.cluster.metrics.connection();
+
 		let mut session = moq_transfork::Session::accept(self.session).await?;
 
-		// Route any subscriptions to the cluster
-		session.route(self.cluster.router);
+		// The token is provided via the URL, or a setup extension for raw QUIC.
+		let permissions = match &self.cluster.auth {
+			Some(auth) => match auth.verify_session(&self.url, session.extensions()) {
+				Ok(permissions) => Some(permissions),
+				Err(err) => {
+					session.close(Error::Unauthorized);
+					return Err(err.context("unauthorized"));
+				}
+			},
+			None => None,
+		};
+
+		let scope = Scope {
+			root: Scope::root(&self.url),
+			permissions,
+		};
+
+		let _metrics = self.cluster.metrics.session(session.clone());
+
+		// Only serve the client if it can subscribe.
+		if session.role().is_publisher() {
+			// Route any subscriptions to the cluster
+			session.route(scope.router(self.cluster.router.clone()))?;
 
-		// TODO things will get weird if locals and remotes announce the same path.
-		session.announce(self.cluster.locals.announced());
-		session.announce(self.cluster.remotes.announced());
+			// Announce tracks from both local and remote origins.
+			let announced = self.cluster.announced_prefix(scope.root.clone());
+			let this = scope.clone();
+			session.announce(rewrite(announced, move |suffix| {
+				this.subscribe(&suffix).map(|_| suffix)
+			}))?;
+		}
 
 		// Add any announcements to the cluster, indicating we're the origin.
-		let all = session.announced(moq_transfork::Path::default());
+		// NOTE: This returns immediately if the client can't publish.
+		let all = session.announced(Path::default());
+		let all = rewrite(all, move |suffix| {
+			let path = scope.publish(&suffix);
+			if path.is_none() {
+				tracing::warn!(?suffix, "unauthorized announce");
+			}
+			path
+		});
+
 		self.cluster.locals.announce(all, Some(session.clone())).await;
 
 		Ok(())
 	}
 }
+
+// The paths that a connection can access.
+//
+// The URL path of the connection acts as the root, so a client connecting to `/room/123` sees and publishes `room/123/*`.
+// Paths are relative to the root on the wire and absolute within the relay.
+#[derive(Clone)]
+struct Scope {
+	root: Path,
+
+	// Checked against the absolute path, if authentication is enabled.
+	permissions: Option<Permissions>,
+}
+
+impl Scope {
+	fn root(url: &Url) -> Path {
+		url.path_segments()
+			.into_iter()
+			.flatten()
+			.filter(|part| !part.is_empty())
+			// The segments are percent-encoded, so decode them to match the paths used by clients.
+			.map(|part| percent_encoding::percent_decode_str(part).decode_utf8_lossy())
+			.fold(Path::default(), |path, part| path.push(part))
+	}
+
+	// Returns the absolute path if the client may subscribe to it.
+	fn subscribe(&self, suffix: &Path) -> Option<Path> {
+		let path = self.root.clone().append(suffix);
+		match &self.permissions {
+			Some(permissions) if !permissions.can_subscribe(&path) => None,
+			_ => Some(path),
+		}
+	}
+
+	// Returns the absolute path if the client may publish to it.
+	fn publish(&self, suffix: &Path) -> Option<Path> {
+		let path = self.root.clone().append(suffix);
+		match &self.permissions {
+			Some(permissions) if !permissions.can_publish(&path) => None,
+			_ => Some(path),
+		}
+	}
+
+	// Returns a router that rewrites the path of any requests, rejecting any the client may not subscribe to.
+	fn router(&self, router: RouterConsumer) -> RouterConsumer {
+		if self.root.is_empty() && self.permissions.is_none() {
+			return router;
+		}
+
+		let (mut producer, consumer) = Router::default().produce();
+		let scope = self.clone();
+
+		tokio::spawn(async move {
+			while let Some(req) = producer.requested().await {
+				let path = match scope.subscribe(&req.track.path) {
+					Some(path) => path,
+					None => {
+						tracing::warn!(path = ?req.track.path, "unauthorized subscribe");
+						req.close(Error::Unauthorized);
+						continue;
+					}
+				};
+
+				let mut track = req.track.clone();
+				track.path = path;
+
+				let router = router.clone();
+				tokio::spawn(async move {
+					match router.subscribe(track).await {
+						Ok(track) => req.serve(track),
+						Err(err) => req.close(err),
+					}
+				});
+			}
+		});
+
+		consumer
+	}
+}
+
+// Returns a copy of the announcements with each path rewritten, skipping any that return None.
+fn rewrite<F>(mut announced: AnnouncedConsumer, f: F) -> AnnouncedConsumer
+where
+	F: Fn(Path) -> Option<Path> + Send + 'static,
+{
+	let mut producer = AnnouncedProducer::new();
+	let consumer = producer.subscribe();
+
+	tokio::spawn(async move {
+		loop {
+			tokio::select! {
+				Some(announced) = announced.next() => match announced {
+					Announced::Active(path, metadata) => {
+						if let Some(path) = f(path) {
+							producer.announce_with(path, metadata);
+						}
+					}
+					Announced::Ended(path) => {
+						if let Some(path) = f(path) {
+							producer.unannounce(&path);
+						}
+					}
+					Announced::Live => {
+						producer.live();
+					}
+				},
+				_ = producer.closed() => return,
+				else => return,
+			}
+		}
+	});
+
+	consumer
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use crate::Claims;
+
+	#[test]
+	fn scope() {
+		let url = Url::parse("https://relay.example.com/room/123/?jwt=ignored").unwrap();
+		let root = Scope::root(&url);
+		assert_eq!(root, Path::default().push("room").push("123"));
+
+		let claims = Claims {
+			publish: vec!["room/123/alice".to_string()],
+			subscribe: vec!["room/123".to_string()],
+			exp: None,
+		};
+
+		let scope = Scope {
+			root,
+			permissions: Some(Permissions::new(&claims)),
+		};
+
+		let alice = Path::default().push("alice").push("video");
+		let bob = Path::default().push("bob");
+
+		assert_eq!(
+			scope.publish(&alice),
+			Some(Path::default().push("room").push("123").append(&alice))
+		);
+		assert_eq!(scope.publish(&bob), None);
+		assert_eq!(
+			scope.subscribe(&bob),
+			Some(Path::default().push("room").push("123").push("bob"))
+		);
+
+		// Each segment is percent-decoded.
+		let url = Url::parse("https://relay.example.com/caf%C3%A9/a%20b").unwrap();
+		assert_eq!(Scope::root(&url), Path::default().push("café").push("a b"));
+
+		// Without authentication, only the root applies.
+		let scope = Scope {
+			root: Scope::root(&Url::parse("https://relay.example.com").unwrap()),
+			permissions: None,
+		};
+		assert_eq!(scope.publish(&bob), Some(bob));
+	}
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use clap::Parser;
+use moq_transfork::{Memory, TrackCache, TrackLimits};
+
+#[derive(Clone, Parser)]
+pub struct LimitsConfig {
+	/// The number of recent groups cached for each track, so new subscribers can rewind.
+	#[arg(long, default_value = "1")]
+	pub cache_groups: usize,
+
+	/// Evict cached groups older than this many seconds, even if there are fewer than --cache-groups.
+	/// For example, --cache-groups 300 --cache-age 5 lets subscribers rewind up to 5 seconds.
+	#[arg(long)]
+	pub cache_age: Option<f64>,
+
+	/// The maximum number of bytes buffered for a single group.
+	/// Larger groups are reset, protecting against publishers that never finish a group.
+	#[arg(long)]
+	pub max_group_bytes: Option<usize>,
+
+	/// The maximum number of bytes buffered for a single track, including groups held by slow subscribers.
+	/// Older groups are evicted to make room, otherwise only this track's groups are reset.
+	#[arg(long)]
+	pub max_track_bytes: Option<usize>,
+
+	/// The maximum number of bytes buffered across all tracks.
+	/// Groups are reset once the budget is exhausted, until memory is freed by older groups.
+	/// Combine with --max-track-bytes so a single track can't exhaust the budget for everybody else.
+	#[arg(long)]
+	pub max_buffer_bytes: Option<usize>,
+}
+
+impl LimitsConfig {
+	// Returns the cache for each relayed track.
+	pub fn cache(&self) -> anyhow::Result<TrackCache> {
+		let age = self
+			.cache_age
+			.map(Duration::try_from_secs_f64)
+			.transpose()
+			.map_err(|_| anyhow::anyhow!("invalid --cache-age"))?;
+
+		Ok(TrackCache {
+			groups: self.cache_groups.max(1),
+			age,
+			..Default::default()
+		})
+	}
+
+	// Returns the limits for each relayed track, sharing a single counter so the total can be reported.
+	pub fn load(&self) -> TrackLimits {
+		let memory = match self.max_buffer_bytes {
+			Some(max) => Memory::limit(max),
+			None => Memory::new(),
+		};
+
+		TrackLimits {
+			group: self.max_group_bytes,
+			track: self.max_track_bytes,
+			memory: Some(memory),
+		}
+	}
+}
@@ -1,10 +1,18 @@
+mod auth;
+mod backoff;
 mod cluster;
 mod connection;
+mod limits;
+mod metrics;
 mod origins;
 mod web;
 
+pub use auth::*;
+pub use backoff::*;
 pub use cluster::*;
 pub use connection::*;
+pub use limits::*;
+pub use metrics::*;
 pub use origins::*;
 pub use web::*;
 
@@ -30,9 +38,21 @@ pub struct Config {
 	#[command(flatten)]
 	pub cluster: ClusterConfig,
 
+	/// Authentication configuration.
+	#[command(flatten)]
+	pub auth: AuthConfig,
+
+	/// Cache and memory limits for relayed tracks.
+	#[command(flatten)]
+	pub limits: LimitsConfig,
+
 	/// Run a web server for debugging purposes.
 	#[arg(long)]
 	pub dev: bool,
+
+	/// Serve Prometheus metrics at /metrics on this TCP address.
+	#[arg(long)]
+	pub metrics_bind: Option<String>,
 }
 
 #[tokio::main]
@@ -51,20 +71,51 @@ async fn main() -> anyhow::Result<()> {
 		anyhow::bail!("missing TLS certificates");
 	}
 
+	let quic = quic::Endpoint::new(quic::Config { bind, tls: tls.clone() })?;
+	let mut server = quic.server.context("missing TLS certificate")?;
+
+	let auth = config.auth.load()?;
+	if auth.is_some() {
+		tracing::info!("authentication enabled");
+	}
+
+	let cluster = Cluster::new(config.cluster.clone(), quic.client, auth, &config.limits)?;
+
 	if config.dev {
 		// Create a web server too.
 		// Currently this only contains the certificate fingerprint (for development only).
-		let web = Web::new(WebConfig { bind, tls: tls.clone() });
+		let web = Web::new(WebConfig {
+			bind,
+			fingerprint: tls.fingerprints.first().cloned(),
+			cluster: None,
+		});
 
 		tokio::spawn(async move {
 			web.run().await.expect("failed to run web server");
 		});
 	}
 
-	let quic = quic::Endpoint::new(quic::Config { bind, tls })?;
-	let mut server = quic.server.context("missing TLS certificate")?;
+	if let Some(metrics) = config.metrics_bind {
+		let metrics = tokio::net::lookup_host(metrics)
+			.await
+			.context("invalid metrics address")?
+			.next()
+			.context("invalid metrics address")?;
+
+		// Serve metrics on a separate address, so they're not exposed publicly.
+		let web = Web::new(WebConfig {
+			bind: metrics,
+			fingerprint: None,
+			cluster: Some(cluster.clone()),
+		});
+
+		tracing::info!(addr = %metrics, "serving metrics");
+
+		tokio::spawn(async move {
+			web.run().await.expect("failed to run metrics server");
+		});
+	}
 
-	let cluster = Cluster::new(config.cluster.clone(), quic.client);
 	let cloned = cluster.clone();
 	tokio::spawn(async move { cloned.run().await.expect("cluster failed") });
 
@@ -72,8 +123,8 @@ async fn main() -> anyhow::Result<()> {
 
 	let mut conn_id = 0;
 
-	while let Some(conn) = server.accept().await {
-		let session = Connection::new(conn_id, conn.into(), cluster.clone());
+	while let Some((url, conn)) = server.accept().await {
+		let session = Connection::new(conn_id, conn.into(), url, cluster.clone());
 		conn_id += 1;
 
 		tokio::spawn(async move {
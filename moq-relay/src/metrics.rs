@@ -0,0 +1,257 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use moq_transfork::Session;
+
+use crate::RemoteState;
+
+// Counters exported in the Prometheus text format via the web server.
+#[derive(Clone, Default)]
+pub struct Metrics {
+	state: Arc<MetricsState>,
+}
+
+#[derive(Default)]
+struct MetricsState {
+	connections: AtomicU64,
+	router_requests: AtomicU64,
+	router_failures: AtomicU64,
+
+	// Active sessions, used to sum the bytes relayed.
+	sessions: Mutex<HashMap<u64, Session>>,
+	next_session: AtomicU64,
+
+	// The bytes relayed by sessions that have closed.
+	bytes_sent: AtomicU64,
+	bytes_received: AtomicU64,
+
+	// The health of each remote node.
+	remotes: Mutex<HashMap<String, RemoteState>>,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Count a session until the guard is dropped, including the bytes it relays.
+	pub fn session(&self, session: Session) -> SessionGuard {
+		let id = self.state.next_session.fetch_add(1, Ordering::Relaxed);
+		self.state.sessions.lock().unwrap().insert(id, session);
+
+		SessionGuard {
+			metrics: self.clone(),
+			id,
+		}
+	}
+
+	// A client connected, which may not result in a session.
+	pub fn connection(&self) {
+		self.state.connections.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn router_request(&self) {
+		self.state.router_requests.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn router_failure(&self) {
+		self.state.router_failures.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn remote_state(&self, host: &str, state: RemoteState) {
+		self.state.remotes.lock().unwrap().insert(host.to_string(), state);
+	}
+
+	pub fn remote_removed(&self, host: &str) {
+		self.state.remotes.lock().unwrap().remove(host);
+	}
+
+	// Returns the (sent, received) bytes for a session.
+	fn bytes(session: &Session) -> (u64, u64) {
+		let total = session.stats().total;
+		(total.bytes_sent, total.bytes_received)
+	}
+
+	/// Render the metrics in the Prometheus text format, including the number of tracks in each origin and the bytes buffered.
+	pub fn render(&self, locals: usize, remotes: usize, buffered: usize) -> String {
+		let state = &self.state;
+
+		let sessions: Vec<Session> = state.sessions.lock().unwrap().values().cloned().collect();
+		let (mut sent, mut received) = (
+			state.bytes_sent.load(Ordering::Relaxed),
+			state.bytes_received.load(Ordering::Relaxed),
+		);
+
+		for session in &sessions {
+			let (s, r) = Self::bytes(session);
+			sent += s;
+			received += r;
+		}
+
+		let mut out = String::new();
+
+		let mut metric = |name: &str, kind: &str, help: &str, values: &[(&str, u64)]| {
+			writeln!(out, "# HELP {} {}", name, help).unwrap();
+			writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+
+			for (labels, value) in values {
+				writeln!(out, "{}{} {}", name, labels, value).unwrap();
+			}
+		};
+
+		metric(
+			"moq_relay_connections_total",
+			"counter",
+			"Connections accepted from clients.",
+			&[("", state.connections.load(Ordering::Relaxed))],
+		);
+		metric(
+			"moq_relay_sessions",
+			"gauge",
+			"Active sessions, including connections to other nodes.",
+			&[("", sessions.len() as u64)],
+		);
+		metric(
+			"moq_relay_tracks",
+			"gauge",
+			"Tracks announced to the relay, by origin.",
+			&[
+				("{origin=\"local\"}", locals as u64),
+				("{origin=\"remote\"}", remotes as u64),
+			],
+		);
+		metric(
+			"moq_relay_router_requests_total",
+			"counter",
+			"Subscriptions routed to an origin.",
+			&[("", state.router_requests.load(Ordering::Relaxed))],
+		);
+		metric(
+			"moq_relay_router_failures_total",
+			"counter",
+			"Subscriptions that could not be routed to an origin.",
+			&[("", state.router_failures.load(Ordering::Relaxed))],
+		);
+
+		let mut remotes: Vec<_> = state.remotes.lock().unwrap().clone().into_iter().collect();
+		remotes.sort_by(|a, b| a.0.cmp(&b.0));
+
+		let up: Vec<_> = remotes
+			.iter()
+			.map(|(host, state)| {
+				let labels = format!("{{host=\"{}\"}}", escape(host));
+				(labels, (*state == RemoteState::Connected) as u64)
+			})
+			.collect();
+		let up: Vec<_> = up.iter().map(|(labels, up)| (labels.as_str(), *up)).collect();
+
+		metric(
+			"moq_relay_remote_up",
+			"gauge",
+			"Whether the session to each remote node is established.",
+			&up,
+		);
+
+		// Each state is a separate series, with a value of 1 for the current state.
+		let states: Vec<_> = remotes
+			.iter()
+			.flat_map(|(host, current)| {
+				RemoteState::ALL.iter().map(move |state| {
+					let labels = format!("{{host=\"{}\",state=\"{}\"}}", escape(host), state);
+					(labels, (state == current) as u64)
+				})
+			})
+			.collect();
+		let states: Vec<_> = states.iter().map(|(labels, value)| (labels.as_str(), *value)).collect();
+
+		metric(
+			"moq_relay_remote_state",
+			"gauge",
+			"The health of the connection to each remote node.",
+			&states,
+		);
+		metric(
+			"moq_relay_buffered_bytes",
+			"gauge",
+			"Bytes buffered in memory for relayed tracks.",
+			&[("", buffered as u64)],
+		);
+		metric(
+			"moq_relay_bytes_sent_total",
+			"counter",
+			"Payload bytes sent to subscribers.",
+			&[("", sent)],
+		);
+		metric(
+			"moq_relay_bytes_received_total",
+			"counter",
+			"Payload bytes received from publishers.",
+			&[("", received)],
+		);
+
+		out
+	}
+}
+
+// Escape a label value, as required by the text format.
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Removes the session from the metrics when dropped, keeping the bytes it relayed.
+pub struct SessionGuard {
+	metrics: Metrics,
+	id: u64,
+}
+
+impl Drop for SessionGuard {
+	fn drop(&mut self) {
+		let state = &self.metrics.state;
+
+		if let Some(session) = state.sessions.lock().unwrap().remove(&self.id) {
+			let (sent, received) = Metrics::bytes(&session);
+			state.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+			state.bytes_received.fetch_add(received, Ordering::Relaxed);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn render() {
+		let metrics = Metrics::new();
+		metrics.connection();
+		metrics.router_request();
+		metrics.router_request();
+		metrics.router_failure();
+		metrics.remote_state("b.example.com", RemoteState::Failing);
+		metrics.remote_state("a.example.com", RemoteState::Connected);
+
+		let out = metrics.render(3, 1, 42);
+
+		assert!(out.contains("# TYPE moq_relay_connections_total counter\nmoq_relay_connections_total 1\n"));
+		assert!(out.contains("moq_relay_tracks{origin=\"local\"} 3\n"));
+		assert!(out.contains("moq_relay_tracks{origin=\"remote\"} 1\n"));
+		assert!(out.contains("moq_relay_router_requests_total 2\n"));
+		assert!(out.contains("moq_relay_router_failures_total 1\n"));
+		assert!(out.contains(
+			"moq_relay_remote_up{host=\"a.example.com\"} 1\nmoq_relay_remote_up{host=\"b.example.com\"} 0\n"
+		));
+		assert!(out.contains("moq_relay_remote_state{host=\"b.example.com\",state=\"failing\"} 1\n"));
+		assert!(out.contains("moq_relay_remote_state{host=\"b.example.com\",state=\"connected\"} 0\n"));
+		assert!(out.contains("moq_relay_buffered_bytes 42\n"));
+		assert!(out.contains("moq_relay_bytes_sent_total 0\n"));
+
+		metrics.remote_removed("b.example.com");
+		assert!(!metrics.render(0, 0, 0).contains("b.example.com"));
+	}
+}
@@ -1,17 +1,26 @@
 use std::{
-	collections::{hash_map, HashMap},
+	collections::{hash_map, HashMap, HashSet},
 	sync::{Arc, Mutex},
 };
 
+use bytes::Bytes;
 use moq_transfork::{Announced, AnnouncedConsumer, AnnouncedProducer, Path, Session};
+use tokio::sync::watch;
+
+// An origin that announced a path, and the metadata it announced.
+// NOTE: None is used when we're the origin, which can't be routed.
+type Route = (Option<Session>, Bytes);
 
 #[derive(Clone)]
 pub struct Origins {
 	// Tracks announced by clients.
 	unique: AnnouncedProducer,
 
-	// Active routes based on path.
-	routes: Arc<Mutex<HashMap<Path, Vec<Option<Session>>>>>,
+	// Active routes based on path, along with the metadata announced by each origin.
+	routes: Arc<Mutex<HashMap<Path, Vec<Route>>>>,
+
+	// Notified whenever the routes change.
+	changed: Arc<watch::Sender<()>>,
 }
 
 impl Default for Origins {
@@ -25,15 +34,30 @@ impl Origins {
 		Self {
 			unique: AnnouncedProducer::new(),
 			routes: Default::default(),
+			changed: Arc::new(watch::channel(()).0),
 		}
 	}
 
 	// Route any announcements from the cluster.
+	//
+	// Any remaining routes are removed when this returns or is cancelled.
 	pub async fn announce(&mut self, mut announced: AnnouncedConsumer, origin: Option<Session>) {
+		let mut active = ActiveRoutes {
+			origins: self.clone(),
+			origin: origin.clone(),
+			paths: HashSet::new(),
+		};
+
 		while let Some(announced) = announced.next().await {
 			match announced {
-				Announced::Active(path) => self.announce_track(path, origin.clone()),
-				Announced::Ended(path) => self.unannounce_track(path, &origin),
+				Announced::Active(path, metadata) => {
+					active.paths.insert(path.clone());
+					self.announce_track(path, origin.clone(), metadata);
+				}
+				Announced::Ended(path) => {
+					active.paths.remove(&path);
+					self.unannounce_track(path, &origin);
+				}
 				Announced::Live => {
 					// Ignore.
 				}
@@ -41,17 +65,37 @@ impl Origins {
 		}
 	}
 
-	fn announce_track(&mut self, path: Path, origin: Option<Session>) {
+	fn announce_track(&mut self, path: Path, origin: Option<Session>, metadata: Bytes) {
 		tracing::info!(?path, "announced origin");
 
 		let mut routes = self.routes.lock().unwrap();
-		match routes.entry(path.clone()) {
-			hash_map::Entry::Occupied(mut entry) => entry.get_mut().push(origin),
-			hash_map::Entry::Vacant(entry) => {
-				entry.insert(vec![origin]);
-				self.unique.announce(path);
+		let origins = match routes.entry(path.clone()) {
+			hash_map::Entry::Occupied(entry) => {
+				let origins = entry.into_mut();
+
+				if let Some(existing) = origins.iter_mut().find(|(s, _)| s == &origin) {
+					// The origin updated its metadata.
+					existing.1 = metadata;
+				} else {
+					origins.push((origin, metadata));
+
+					// The most recent origin wins, as the others are likely stale (ex. a reconnecting publisher).
+					tracing::warn!(
+						?path,
+						origins = origins.len(),
+						"conflicting origin, preferring most recent"
+					);
+				}
+
+				origins
 			}
-		}
+			hash_map::Entry::Vacant(entry) => entry.insert(vec![(origin, metadata)]),
+		};
+
+		// Advertise the metadata of the most recent origin.
+		let metadata = origins.last().unwrap().1.clone();
+		self.unique.announce_with(path, metadata);
+		self.changed.send_replace(());
 	}
 
 	fn unannounce_track(&mut self, path: Path, origin: &Option<Session>) {
@@ -63,14 +107,39 @@ impl Origins {
 			hash_map::Entry::Vacant(_) => return,
 		};
 
+		let active = Self::choose(entry).cloned();
+
 		// Technically this is wrong, as it will remove more than one None value.
 		// But currently there can only be one None that will never be removed, so it's fine.
-		entry.retain(|s| s != origin);
+		entry.retain(|(s, _)| s != origin);
 
-		if entry.is_empty() {
+		if let Some((_, metadata)) = entry.last() {
+			// Advertise the metadata of the remaining origin, if it changed.
+			self.unique.announce_with(path.clone(), metadata.clone());
+
+			if active.is_some() && &active == origin {
+				tracing::info!(?path, origins = entry.len(), "routing to next origin");
+			}
+		} else {
 			routes.remove(&path);
 			self.unique.unannounce(&path);
 		}
+
+		self.changed.send_replace(());
+	}
+
+	// The number of unique paths that can be routed.
+	pub fn len(&self) -> usize {
+		self.routes.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// Returns a receiver that's notified whenever a route changes.
+	pub fn changed(&self) -> watch::Receiver<()> {
+		self.changed.subscribe()
 	}
 
 	pub fn announced(&self) -> AnnouncedConsumer {
@@ -81,11 +150,148 @@ impl Origins {
 		self.unique.subscribe_prefix(prefix)
 	}
 
+	// Returns the session to use for a subscription to the path.
+	//
+	// The cluster calls this again when the chosen origin's session closes, failing over any live subscriptions.
 	pub fn route(&self, path: &Path) -> Option<Session> {
-		// Return the session that most recently announced the path.
 		let routes = self.routes.lock().unwrap();
+		Self::choose(routes.get(path)?).cloned()
+	}
+
+	// Return the session that most recently announced the path.
+	// NOTE: None is used when we're the origin, which can't be routed.
+	fn choose(origins: &[Route]) -> Option<&Session> {
+		origins.iter().rev().find_map(|(session, _)| session.as_ref())
+	}
+}
+
+// The routes added by a single call to [Origins::announce], removed when dropped.
+struct ActiveRoutes {
+	origins: Origins,
+	origin: Option<Session>,
+	paths: HashSet<Path>,
+}
+
+impl Drop for ActiveRoutes {
+	fn drop(&mut self) {
+		for path in self.paths.drain() {
+			self.origins.unannounce_track(path, &self.origin);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use moq_transfork::loopback;
+
+	async fn session() -> Session {
+		let (client, server) = loopback::connect().await;
+		let (client, _server) = tokio::join!(Session::connect(client), Session::accept(server));
+		client.unwrap()
+	}
+
+	#[tokio::test]
+	async fn failover() {
+		let mut origins = Origins::new();
+		let mut announced = origins.announced();
+		let path = Path::default().push("room").push("alice");
+
+		let first = session().await;
+		let second = session().await;
+
+		// Each origin uses different metadata so we can wait for the advertised changes.
+		let mut a = AnnouncedProducer::new();
+		a.announce_with(path.clone(), Bytes::from_static(b"a"));
+		let mut b = AnnouncedProducer::new();
+		b.announce_with(path.clone(), Bytes::from_static(b"b"));
+
+		let (mut this, consumer, origin) = (origins.clone(), a.subscribe(), Some(first.clone()));
+		tokio::spawn(async move { this.announce(consumer, origin).await });
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"a")))
+		);
+		assert!(origins.route(&path) == Some(first.clone()));
+
+		// The most recent origin wins.
+		let (mut this, consumer, origin) = (origins.clone(), b.subscribe(), Some(second.clone()));
+		tokio::spawn(async move { this.announce(consumer, origin).await });
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"b")))
+		);
+		assert!(origins.route(&path) == Some(second.clone()));
+		assert_eq!(origins.len(), 1);
+
+		// Fail over to the previous origin when the chosen one goes away.
+		drop(b);
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"a")))
+		);
+		assert!(origins.route(&path) == Some(first.clone()));
+
+		drop(a);
+		assert_eq!(announced.next().await, Some(Announced::Ended(path.clone())));
+		assert!(origins.route(&path).is_none());
+		assert!(origins.is_empty());
+
+		// Routes are also removed when the announcements are cancelled.
+		let mut c = AnnouncedProducer::new();
+		c.announce(path.clone());
+
+		let (mut this, consumer, origin) = (origins.clone(), c.subscribe(), Some(first.clone()));
+		let handle = tokio::spawn(async move { this.announce(consumer, origin).await });
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::new()))
+		);
+		assert!(origins.route(&path) == Some(first.clone()));
+
+		handle.abort();
+		assert_eq!(announced.next().await, Some(Announced::Ended(path.clone())));
+		assert!(origins.route(&path).is_none());
+
+		// We can't route to ourselves, but it's not an error.
+		origins.announce_track(path.clone(), None, Bytes::new());
+		assert!(origins.route(&path).is_none());
+	}
+
+	#[tokio::test]
+	async fn metadata() {
+		let mut origins = Origins::new();
+		let mut announced = origins.announced();
+		let path = Path::default().push("room").push("alice");
+
+		let first = session().await;
+		let second = session().await;
+
+		origins.announce_track(path.clone(), Some(first.clone()), Bytes::from_static(b"first"));
+		origins.announce_track(path.clone(), Some(second.clone()), Bytes::from_static(b"second"));
+
+		// The metadata of the most recent origin is advertised.
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"second")))
+		);
+
+		// Updating the metadata doesn't add another origin.
+		origins.announce_track(path.clone(), Some(second.clone()), Bytes::from_static(b"updated"));
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"updated")))
+		);
+
+		// Failing over advertises the remaining origin's metadata.
+		origins.unannounce_track(path.clone(), &Some(second));
+		assert_eq!(
+			announced.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"first")))
+		);
 
-		let available = routes.get(path)?;
-		available.iter().find(|route| route.is_some()).cloned().unwrap()
+		origins.unannounce_track(path.clone(), &Some(first));
+		assert_eq!(announced.next().await, Some(Announced::Ended(path)));
 	}
 }
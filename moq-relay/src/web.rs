@@ -1,16 +1,29 @@
 use std::net;
 
-use axum::{extract::State, http::Method, response::IntoResponse, routing::get, Router};
+use axum::{
+	extract::State,
+	http::{header, Method},
+	response::IntoResponse,
+	routing::get,
+	Router,
+};
 use hyper_serve::accept::DefaultAcceptor;
 use tower_http::cors::{Any, CorsLayer};
 
+use crate::Cluster;
+
 pub struct WebConfig {
 	pub bind: net::SocketAddr,
-	pub tls: moq_native::tls::Config,
+
+	/// Serve the certificate fingerprint at /fingerprint, for development only.
+	pub fingerprint: Option<String>,
+
+	/// Serve Prometheus metrics for the cluster at /metrics.
+	pub cluster: Option<Cluster>,
 }
 
 // Run a HTTP server using Axum
-// TODO remove this when Chrome adds support for self-signed certificates using WebTransport
+// TODO remove the fingerprint when Chrome adds support for self-signed certificates using WebTransport
 pub struct Web {
 	app: Router,
 	server: hyper_serve::Server<DefaultAcceptor>,
@@ -18,15 +31,19 @@ pub struct Web {
 
 impl Web {
 	pub fn new(config: WebConfig) -> Self {
-		// Get the first certificate's fingerprint.
+		let mut app = Router::new();
+
+		// Serve the first certificate's fingerprint.
 		// TODO serve all of them so we can support multiple signature algorithms.
-		let fingerprint = config.tls.fingerprints.first().expect("missing certificate").clone();
+		if let Some(fingerprint) = config.fingerprint {
+			app = app.route("/fingerprint", get(serve_fingerprint).with_state(fingerprint));
+		}
 
-		let app = Router::new()
-			.route("/fingerprint", get(serve_fingerprint))
-			.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]))
-			.with_state(fingerprint);
+		if let Some(cluster) = config.cluster {
+			app = app.route("/metrics", get(serve_metrics).with_state(cluster));
+		}
 
+		let app = app.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]));
 		let server = hyper_serve::bind(config.bind);
 
 		Self { app, server }
@@ -41,3 +58,11 @@ impl Web {
 async fn serve_fingerprint(State(fingerprint): State<String>) -> impl IntoResponse {
 	fingerprint
 }
+
+async fn serve_metrics(State(cluster): State<Cluster>) -> impl IntoResponse {
+	let buffered = cluster.limits.memory.as_ref().map_or(0, |memory| memory.used());
+	let body = cluster
+		.metrics
+		.render(cluster.locals.len(), cluster.remotes.len(), buffered);
+	([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
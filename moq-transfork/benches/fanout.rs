@@ -0,0 +1,231 @@
+//! Measures the throughput of relaying a single track to N subscribers, all on a single core.
+//!
+//! A publisher session is subscribed to once by a relay session, which serves the track to each subscriber.
+//! This is the same forwarding path used by moq-relay, minus the network.
+//!
+//! The chunk forwarding is also measured on its own, comparing the batched path against the previous per-chunk path:
+//! - `per-chunk`: each chunk is written with [FrameProducer::write] and forwarded after [FrameConsumer::read].
+//! - `batched`: available chunks are written with [FrameProducer::write_chunks] and forwarded after [FrameConsumer::read_chunks].
+//!
+//! Run with `cargo bench -p moq-transfork --features loopback --bench fanout -- [SUBSCRIBERS...]`
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use moq_transfork::{
+	loopback, FrameConsumer, FrameProducer, Group, Session, Track, TrackCache, TrackConsumer, TrackRequest,
+};
+
+const GROUPS: u64 = 100;
+const FRAMES: usize = 10;
+const FRAME_SIZE: usize = 4096;
+
+// Roughly the payload of a QUIC packet, and how many of them are available each time the stream is read.
+const CHUNK_SIZE: usize = 1024;
+const CHUNK_BATCH: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+enum Forward {
+	PerChunk,
+	Batched,
+}
+
+fn main() {
+	let subscribers: Vec<usize> = std::env::args()
+		.skip(1)
+		.filter(|arg| !arg.starts_with("--")) // ignore flags added by cargo, ex. --bench
+		.map(|arg| arg.parse().expect("invalid number of subscribers"))
+		.collect();
+
+	let subscribers = match subscribers.is_empty() {
+		true => vec![1, 4, 16],
+		false => subscribers,
+	};
+
+	// A single thread, so the results are per core.
+	let runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	for count in subscribers {
+		let elapsed = runtime.block_on(fanout(count));
+
+		let bytes = GROUPS as usize * FRAMES * FRAME_SIZE * count;
+		let throughput = bytes as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+
+		println!(
+			"subscribers={:<4} delivered={:>6.1}MB elapsed={:>8.2?} throughput={:>8.1}MB/s",
+			count,
+			bytes as f64 / 1_000_000.0,
+			elapsed,
+			throughput
+		);
+
+		let per_chunk = runtime.block_on(forward(count, Forward::PerChunk));
+		let batched = runtime.block_on(forward(count, Forward::Batched));
+
+		println!(
+			"subscribers={:<4} forward per-chunk={:>8.2?} batched={:>8.2?} speedup={:>5.2}x",
+			count,
+			per_chunk,
+			batched,
+			per_chunk.as_secs_f64() / batched.as_secs_f64()
+		);
+	}
+}
+
+async fn connect() -> (Session, Session) {
+	let (client, server) = loopback::connect().await;
+	let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+	(client.unwrap(), server.unwrap())
+}
+
+// Keep every group around, so nothing is skipped if a subscriber falls behind.
+fn track() -> Track {
+	let cache = TrackCache {
+		groups: GROUPS as usize,
+		..Default::default()
+	};
+
+	Track::build().path("bench").cache(cache).into()
+}
+
+// Request every group, so the subscription ends once they've all been delivered.
+fn request() -> TrackRequest {
+	TrackRequest::default().groups(..GROUPS)
+}
+
+async fn fanout(subscribers: usize) -> Duration {
+	let (relay, mut publisher) = connect().await;
+
+	let (mut producer, consumer) = track().produce();
+	publisher.publish(consumer).unwrap();
+
+	// The relay subscribes upstream once.
+	let upstream = relay.subscribe_request(track(), request());
+	upstream.info().await.unwrap();
+
+	let mut consumers = Vec::new();
+	let mut sessions = Vec::new();
+
+	for _ in 0..subscribers {
+		let (subscriber, mut downstream) = connect().await;
+		downstream.publish(upstream.clone()).unwrap();
+
+		let consumer = subscriber.subscribe_request(track(), request());
+		consumer.info().await.unwrap();
+
+		consumers.push(consumer);
+		sessions.push((subscriber, downstream));
+	}
+
+	let start = Instant::now();
+
+	let readers: Vec<_> = consumers
+		.into_iter()
+		.map(|consumer| tokio::spawn(read(consumer)))
+		.collect();
+
+	let frame = Bytes::from(vec![0u8; FRAME_SIZE]);
+	for _ in 0..GROUPS {
+		let mut group = producer.append_group();
+		for _ in 0..FRAMES {
+			group.write_frame(frame.clone());
+		}
+
+		// Let the relay make progress, as it would with a real publisher.
+		tokio::task::yield_now().await;
+	}
+
+	for reader in readers {
+		let received = reader.await.unwrap();
+		assert_eq!(received, GROUPS as usize * FRAMES * FRAME_SIZE, "missing data");
+	}
+
+	start.elapsed()
+}
+
+// Read every group, returning the number of bytes received.
+async fn read(consumer: TrackConsumer) -> usize {
+	let mut consumer = consumer.ascending(Duration::from_secs(10));
+	let mut received = 0;
+
+	while let Some(mut group) = consumer.next_group().await.unwrap() {
+		while let Some(frame) = group.read_frame().await.unwrap() {
+			received += frame.len();
+		}
+	}
+
+	received
+}
+
+// Forward chunks from a single producer to each subscriber, copying them into a buffer like a stream would.
+async fn forward(subscribers: usize, mode: Forward) -> Duration {
+	let start = Instant::now();
+
+	let chunk = Bytes::from(vec![0u8; CHUNK_SIZE]);
+
+	for sequence in 0..GROUPS {
+		let (mut group, _) = Group::new(sequence).produce();
+
+		for _ in 0..FRAMES {
+			let mut frame = group.create_frame(FRAME_SIZE);
+
+			let writers: Vec<_> = (0..subscribers)
+				.map(|_| tokio::spawn(write(frame.subscribe(), mode)))
+				.collect();
+
+			let mut remain = FRAME_SIZE;
+			while remain > 0 {
+				let batch = (0..CHUNK_BATCH).map_while(|_| {
+					let size = remain.min(CHUNK_SIZE);
+					remain -= size;
+					(size > 0).then(|| chunk.slice(..size))
+				});
+
+				receive(&mut frame, batch.collect(), mode);
+
+				// Let the subscribers make progress, as if we were waiting for the next packet.
+				tokio::task::yield_now().await;
+			}
+
+			drop(frame);
+
+			for writer in writers {
+				assert_eq!(writer.await.unwrap(), FRAME_SIZE, "missing data");
+			}
+		}
+	}
+
+	start.elapsed()
+}
+
+// Write the chunks received from the network into the frame.
+fn receive(frame: &mut FrameProducer, chunks: Vec<Bytes>, mode: Forward) {
+	match mode {
+		Forward::PerChunk => chunks.into_iter().for_each(|chunk| frame.write(chunk)),
+		Forward::Batched => frame.write_chunks(chunks),
+	}
+}
+
+// Copy the frame into a send buffer, returning the number of bytes written.
+async fn write(mut frame: FrameConsumer, mode: Forward) -> usize {
+	let mut buf = Vec::with_capacity(FRAME_SIZE);
+
+	match mode {
+		Forward::PerChunk => {
+			while let Some(chunk) = frame.read().await.unwrap() {
+				buf.extend_from_slice(&chunk);
+			}
+		}
+		Forward::Batched => {
+			while let Some(chunks) = frame.read_chunks().await.unwrap() {
+				for chunk in chunks {
+					buf.extend_from_slice(&chunk);
+				}
+			}
+		}
+	}
+
+	buf.len()
+}
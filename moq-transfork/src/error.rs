@@ -46,6 +46,22 @@ pub enum Error {
 
 	#[error("protocol violation")]
 	ProtocolViolation,
+
+	/// The roles of both endpoints don't allow publishing or subscribing.
+	#[error("incompatible roles: ours={0:?} theirs={1:?}")]
+	RoleIncompatible(message::Role, message::Role),
+
+	/// The operation isn't allowed by the negotiated role.
+	#[error("role violation: {0:?}")]
+	RoleViolation(message::Role),
+
+	/// The peer isn't allowed to access the path.
+	#[error("unauthorized")]
+	Unauthorized,
+
+	/// A group would exceed the configured memory limits.
+	#[error("too large")]
+	TooLarge,
 }
 
 impl Error {
@@ -63,6 +79,10 @@ impl Error {
 			Self::NotFound => 13,
 			Self::WrongSize => 14,
 			Self::ProtocolViolation => 15,
+			Self::RoleIncompatible(..) => 16,
+			Self::RoleViolation(_) => 17,
+			Self::Unauthorized => 18,
+			Self::TooLarge => 19,
 			Self::App(app) => *app + 64,
 		}
 	}
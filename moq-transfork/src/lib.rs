@@ -37,6 +37,8 @@ mod model;
 mod session;
 
 pub mod coding;
+#[cfg(any(test, feature = "loopback"))]
+pub mod loopback;
 pub mod message;
 pub use error::*;
 pub use model::*;
@@ -0,0 +1,400 @@
+//! An in-memory transport, used to connect two sessions within the same runtime without any sockets.
+//!
+//! Each side gets a real [quinn] endpoint, but UDP datagrams are passed between them via a shared queue.
+//! Faults can be injected via [Config]: datagrams can be delayed or lost, in which case QUIC will retransmit,
+//! and unidirectional (data) streams can be reset partway through.
+//!
+//! This requires the `loopback` feature.
+use std::{
+	collections::VecDeque,
+	fmt, io,
+	net::{Ipv4Addr, SocketAddr},
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{Context, Poll, Waker},
+	time::Duration,
+};
+
+use quinn::udp::{RecvMeta, Transmit};
+
+// How long to stall a stream before resetting it.
+const RESET_DELAY: Duration = Duration::from_millis(10);
+
+/// The faults to inject into a loopback connection.
+#[derive(Clone, Debug)]
+pub struct Config {
+	/// Delay every datagram, in each direction.
+	pub delay: Duration,
+
+	/// The probability that a datagram is lost, between 0 and 1.
+	pub loss: f64,
+
+	/// The probability that a unidirectional stream is reset before it finishes, between 0 and 1.
+	pub reset: f64,
+
+	/// The number of bytes to forward before resetting a stream.
+	pub reset_after: usize,
+
+	/// The error code used when resetting a stream.
+	pub reset_code: u32,
+
+	/// Used to seed the random number generator, so faults are reproducible.
+	pub seed: u64,
+
+	/// The maximum number of concurrent unidirectional streams each side may open.
+	pub max_streams: u32,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			delay: Duration::ZERO,
+			loss: 0.0,
+			reset: 0.0,
+			reset_after: 0,
+			reset_code: 0,
+			seed: 1,
+			max_streams: 100,
+		}
+	}
+}
+
+/// Establish a connected pair of WebTransport sessions without any faults, returning (client, server).
+pub async fn connect() -> (web_transport::Session, web_transport::Session) {
+	connect_with(Config::default()).await
+}
+
+/// Establish a connected pair of WebTransport sessions with the given faults, returning (client, server).
+///
+/// NOTE: Streams are proxied when resets are enabled, so stream priorities are not respected.
+pub async fn connect_with(config: Config) -> (web_transport::Session, web_transport::Session) {
+	let (client, server) = pair(&config).await;
+
+	if config.reset <= 0.0 {
+		return (client, server);
+	}
+
+	// Insert a proxy that can reset streams, as quinn won't let us reset streams opened by the peer.
+	let (proxy_client, proxy_server) = pair(&Config {
+		max_streams: config.max_streams,
+		..Default::default()
+	})
+	.await;
+
+	let rng = Arc::new(Mutex::new(Rng::new(config.seed)));
+	tokio::spawn(proxy(server, proxy_client, config, rng));
+
+	(client, proxy_server)
+}
+
+// Establish a pair of QUIC connections using in-memory sockets.
+async fn pair(config: &Config) -> (web_transport::Session, web_transport::Session) {
+	let client_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+	let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 2));
+
+	let client_socket = Arc::new(Socket::new(client_addr, config, config.seed));
+	let server_socket = Arc::new(Socket::new(server_addr, config, config.seed.wrapping_add(1)));
+
+	client_socket.connect(&server_socket);
+	server_socket.connect(&client_socket);
+
+	let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+	let key = quinn::rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+
+	let provider = Arc::new(quinn::rustls::crypto::ring::default_provider());
+
+	let mut server_config = quinn::rustls::ServerConfig::builder_with_provider(provider.clone())
+		.with_protocol_versions(&[&quinn::rustls::version::TLS13])
+		.unwrap()
+		.with_no_client_auth()
+		.with_single_cert(vec![cert.cert.der().clone()], key.into())
+		.unwrap();
+	server_config.alpn_protocols = vec![crate::ALPN.to_vec()];
+
+	let mut roots = quinn::rustls::RootCertStore::empty();
+	roots.add(cert.cert.der().clone()).unwrap();
+
+	let mut client_config = quinn::rustls::ClientConfig::builder_with_provider(provider)
+		.with_protocol_versions(&[&quinn::rustls::version::TLS13])
+		.unwrap()
+		.with_root_certificates(roots)
+		.with_no_client_auth();
+	client_config.alpn_protocols = vec![crate::ALPN.to_vec()];
+
+	let mut transport = quinn::TransportConfig::default();
+	transport.max_concurrent_uni_streams(config.max_streams.into());
+	let transport = Arc::new(transport);
+
+	let server_config: quinn::crypto::rustls::QuicServerConfig = server_config.try_into().unwrap();
+	let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(server_config));
+	server_config.transport_config(transport.clone());
+
+	let client_config: quinn::crypto::rustls::QuicClientConfig = client_config.try_into().unwrap();
+	let mut client_config = quinn::ClientConfig::new(Arc::new(client_config));
+	client_config.transport_config(transport);
+
+	let runtime = Arc::new(quinn::TokioRuntime);
+
+	let server = quinn::Endpoint::new_with_abstract_socket(
+		Default::default(),
+		Some(server_config),
+		server_socket,
+		runtime.clone(),
+	)
+	.unwrap();
+
+	let client = quinn::Endpoint::new_with_abstract_socket(Default::default(), None, client_socket, runtime).unwrap();
+
+	let connecting = client.connect_with(client_config, server_addr, "localhost").unwrap();
+
+	let (client, server) = tokio::join!(connecting, async { server.accept().await.unwrap().await });
+
+	let client = web_transport::quinn::Session::from(client.unwrap());
+	let server = web_transport::quinn::Session::from(server.unwrap());
+
+	(client.into(), server.into())
+}
+
+// Forward streams in both directions until either session is closed.
+async fn proxy(mut a: web_transport::Session, mut b: web_transport::Session, config: Config, rng: Arc<Mutex<Rng>>) {
+	let res = tokio::select! {
+		res = forward(a.clone(), b.clone(), &config, &rng) => res,
+		res = forward(b.clone(), a.clone(), &config, &rng) => res,
+	};
+
+	if let Err(err) = res {
+		tracing::debug!(?err, "loopback proxy closed");
+	}
+
+	a.close(0, "proxy closed");
+	b.close(0, "proxy closed");
+}
+
+async fn forward(
+	mut from: web_transport::Session,
+	mut to: web_transport::Session,
+	config: &Config,
+	rng: &Mutex<Rng>,
+) -> Result<(), web_transport::Error> {
+	let mut from_bi = from.clone();
+
+	loop {
+		tokio::select! {
+			res = from.accept_uni() => {
+				let recv = res?;
+				let send = to.open_uni().await?;
+
+				let reset = rng.lock().unwrap().chance(config.reset).then_some((config.reset_after, config.reset_code));
+				tokio::spawn(copy(recv, send, reset));
+			}
+			res = from_bi.accept_bi() => {
+				let (send_from, recv_from) = res?;
+				let (send_to, recv_to) = to.open_bi().await?;
+
+				tokio::spawn(copy(recv_from, send_to, None));
+				tokio::spawn(copy(recv_to, send_from, None));
+			}
+		}
+	}
+}
+
+// Copy a stream, optionally resetting it after (at least) the given number of bytes.
+async fn copy(mut recv: web_transport::RecvStream, mut send: web_transport::SendStream, reset: Option<(usize, u32)>) {
+	let mut size = 0;
+
+	loop {
+		match recv.read(usize::MAX).await {
+			Ok(Some(chunk)) => {
+				if let Err(err) = send.write(&chunk).await {
+					recv.stop(error_code(&err));
+					return;
+				}
+
+				size += chunk.len();
+
+				if let Some((_, code)) = reset.filter(|(after, _)| size >= *after) {
+					// A reset discards any unread data, so give the receiver a chance to read what we forwarded.
+					tokio::time::sleep(RESET_DELAY).await;
+
+					send.reset(code);
+					recv.stop(code);
+					return;
+				}
+			}
+			// Dropping the stream sends a FIN.
+			Ok(None) => return,
+			Err(err) => {
+				send.reset(error_code(&err));
+				return;
+			}
+		}
+	}
+}
+
+// Propagate the reset or stop code if there is one.
+fn error_code(err: &web_transport::Error) -> u32 {
+	match err {
+		web_transport::Error::Read(web_transport::quinn::ReadError::Reset(code)) => *code,
+		web_transport::Error::Write(web_transport::quinn::WriteError::Stopped(code)) => *code,
+		_ => 0,
+	}
+}
+
+// A tiny xorshift generator, so faults are reproducible without any dependencies.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+	fn new(seed: u64) -> Self {
+		// Zero is the only invalid state.
+		Self(seed.max(1))
+	}
+
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	// Returns true with the given probability.
+	fn chance(&mut self, probability: f64) -> bool {
+		probability > 0.0 && (self.next() as f64 / u64::MAX as f64) < probability
+	}
+}
+
+#[derive(Default)]
+struct Queue {
+	datagrams: VecDeque<(SocketAddr, Vec<u8>)>,
+	waker: Option<Waker>,
+}
+
+impl Queue {
+	fn push(&mut self, addr: SocketAddr, datagram: Vec<u8>) {
+		self.datagrams.push_back((addr, datagram));
+
+		if let Some(waker) = self.waker.take() {
+			waker.wake();
+		}
+	}
+}
+
+/// A fake UDP socket that delivers datagrams to the connected peer, possibly delayed or lost.
+struct Socket {
+	addr: SocketAddr,
+	inbox: Arc<Mutex<Queue>>,
+	peer: Mutex<Option<Arc<Mutex<Queue>>>>,
+
+	delay: Duration,
+	loss: f64,
+	rng: Mutex<Rng>,
+}
+
+impl Socket {
+	fn new(addr: SocketAddr, config: &Config, seed: u64) -> Self {
+		Self {
+			addr,
+			inbox: Default::default(),
+			peer: Default::default(),
+			delay: config.delay,
+			loss: config.loss,
+			rng: Mutex::new(Rng::new(seed)),
+		}
+	}
+
+	fn connect(&self, peer: &Socket) {
+		self.peer.lock().unwrap().replace(peer.inbox.clone());
+	}
+}
+
+impl fmt::Debug for Socket {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Socket").field("addr", &self.addr).finish()
+	}
+}
+
+impl quinn::AsyncUdpSocket for Socket {
+	fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn quinn::UdpPoller>> {
+		Box::pin(Writable)
+	}
+
+	fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+		let peer = self.peer.lock().unwrap().clone();
+		let peer = peer.ok_or(io::ErrorKind::NotConnected)?;
+
+		// Pretend we sent it.
+		if self.rng.lock().unwrap().chance(self.loss) {
+			return Ok(());
+		}
+
+		let addr = self.addr;
+		let datagram = transmit.contents.to_vec();
+
+		if self.delay.is_zero() {
+			peer.lock().unwrap().push(addr, datagram);
+		} else {
+			let delay = self.delay;
+			tokio::spawn(async move {
+				tokio::time::sleep(delay).await;
+				peer.lock().unwrap().push(addr, datagram);
+			});
+		}
+
+		Ok(())
+	}
+
+	fn poll_recv(
+		&self,
+		cx: &mut Context,
+		bufs: &mut [io::IoSliceMut<'_>],
+		meta: &mut [RecvMeta],
+	) -> Poll<io::Result<usize>> {
+		let mut queue = self.inbox.lock().unwrap();
+
+		let mut count = 0;
+		while count < bufs.len().min(meta.len()) {
+			let (addr, datagram) = match queue.datagrams.pop_front() {
+				Some(datagram) => datagram,
+				None => break,
+			};
+
+			let len = datagram.len().min(bufs[count].len());
+			bufs[count][..len].copy_from_slice(&datagram[..len]);
+
+			meta[count] = RecvMeta {
+				addr,
+				len,
+				stride: len,
+				ecn: None,
+				dst_ip: Some(self.addr.ip()),
+			};
+
+			count += 1;
+		}
+
+		if count > 0 {
+			return Poll::Ready(Ok(count));
+		}
+
+		queue.waker.replace(cx.waker().clone());
+		Poll::Pending
+	}
+
+	fn local_addr(&self) -> io::Result<SocketAddr> {
+		Ok(self.addr)
+	}
+
+	fn may_fragment(&self) -> bool {
+		false
+	}
+}
+
+/// The in-memory socket is always writable.
+#[derive(Debug)]
+struct Writable;
+
+impl quinn::UdpPoller for Writable {
+	fn poll_writable(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
@@ -1,6 +1,8 @@
+use bytes::Bytes;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use crate::coding::*;
+use crate::message::{Version, Versioned};
 use crate::Path;
 
 /// Send by the publisher, used to determine the message that follows.
@@ -16,57 +18,132 @@ enum AnnounceStatus {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Announce {
 	Ended { suffix: Path },
-	Active { suffix: Path },
+	Active { suffix: Path, metadata: Bytes },
 	Live,
 }
 
-impl Decode for Announce {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+impl Announce {
+	/// The maximum size of the metadata, so a peer can't make us buffer an unbounded amount.
+	pub const MAX_METADATA: usize = 64 * 1024;
+}
+
+impl Versioned for Announce {
+	fn decode_version<R: bytes::Buf>(r: &mut R, version: Version) -> Result<Self, DecodeError> {
 		Ok(match AnnounceStatus::decode(r)? {
 			AnnounceStatus::Ended => Self::Ended {
 				suffix: Path::decode(r)?,
 			},
-			AnnounceStatus::Active => Self::Active {
-				suffix: Path::decode(r)?,
-			},
+			AnnounceStatus::Active => {
+				let suffix = Path::decode(r)?;
+				let metadata = match version >= Version::FORK_08 {
+					true => {
+						let size = usize::decode(r)?;
+						if size > Self::MAX_METADATA {
+							return Err(DecodeError::BoundsExceeded);
+						}
+
+						if r.remaining() < size {
+							return Err(DecodeError::Short);
+						}
+
+						r.copy_to_bytes(size)
+					}
+					false => Bytes::new(),
+				};
+
+				Self::Active { suffix, metadata }
+			}
 			AnnounceStatus::Live => Self::Live,
 		})
 	}
-}
 
-impl Encode for Announce {
-	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
 		match self {
 			Self::Ended { suffix } => {
 				AnnounceStatus::Ended.encode(w);
 				suffix.encode(w);
 			}
-			Self::Active { suffix } => {
+			Self::Active { suffix, metadata } => {
 				AnnounceStatus::Active.encode(w);
 				suffix.encode(w);
+
+				// The metadata is dropped for older versions.
+				if version >= Version::FORK_08 {
+					metadata.encode(w);
+				}
 			}
 			Self::Live => AnnounceStatus::Live.encode(w),
 		}
 	}
 }
 
+impl Decode for Announce {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Self::decode_version(r, Version::CURRENT)
+	}
+}
+
+impl Encode for Announce {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.encode_version(w, Version::CURRENT)
+	}
+}
+
 /// Sent by the subscriber to request ANNOUNCE messages.
 #[derive(Clone, Debug)]
 pub struct AnnouncePlease {
 	/// The desired track prefix
 	pub prefix: Path,
+
+	/// Only announce suffixes that start with this pattern, where a `*` part matches any single part.
+	pub pattern: Path,
+
+	/// The maximum number of parts in each suffix, or None for any depth.
+	pub depth: Option<u64>,
+}
+
+impl Versioned for AnnouncePlease {
+	fn decode_version<R: bytes::Buf>(r: &mut R, version: Version) -> Result<Self, DecodeError> {
+		let prefix = Path::decode(r)?;
+
+		let (pattern, depth) = match version >= Version::FORK_07 {
+			true => {
+				let pattern = Path::decode(r)?;
+				let depth = match u64::decode(r)? {
+					0 => None,
+					n => Some(n - 1),
+				};
+
+				(pattern, depth)
+			}
+			false => (Path::default(), None),
+		};
+
+		Ok(Self { prefix, pattern, depth })
+	}
+
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
+		self.prefix.encode(w);
+
+		// Older versions can't express a filter, so the subscriber filters the announcements instead.
+		if version >= Version::FORK_07 {
+			self.pattern.encode(w);
+			// A depth too large to encode is clamped, which is effectively unlimited anyway.
+			let depth = self.depth.map(|v| v.saturating_add(1).min(VarInt::MAX.into_inner()));
+			depth.unwrap_or(0).encode(w);
+		}
+	}
 }
 
 impl Decode for AnnouncePlease {
 	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let prefix = Path::decode(r)?;
-		Ok(Self { prefix })
+		Self::decode_version(r, Version::CURRENT)
 	}
 }
 
 impl Encode for AnnouncePlease {
 	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
-		self.prefix.encode(w)
+		self.encode_version(w, Version::CURRENT)
 	}
 }
 
@@ -87,3 +164,94 @@ impl Encode for AnnounceStatus {
 		(*self as u8).encode(w)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn announce_versions() {
+		let announce = Announce::Active {
+			suffix: Path::default().push("alice"),
+			metadata: Bytes::from_static(b"Alice"),
+		};
+
+		for version in Version::SUPPORTED {
+			let mut buf = Vec::new();
+			announce.encode_version(&mut buf, version);
+
+			let mut r = buf.as_slice();
+			let decoded = Announce::decode_version(&mut r, version).unwrap();
+			assert!(r.is_empty());
+
+			// Older versions can't carry metadata.
+			let expected = match version >= Version::FORK_08 {
+				true => announce.clone(),
+				false => Announce::Active {
+					suffix: Path::default().push("alice"),
+					metadata: Bytes::new(),
+				},
+			};
+			assert_eq!(decoded, expected);
+		}
+	}
+
+	#[test]
+	fn announce_metadata_max() {
+		let announce = Announce::Active {
+			suffix: Path::default().push("alice"),
+			metadata: Bytes::from(vec![0; Announce::MAX_METADATA + 1]),
+		};
+
+		// Rejected as soon as the size is known, without waiting for the rest.
+		let mut buf = Vec::new();
+		announce.encode_version(&mut buf, Version::CURRENT);
+		buf.truncate(16);
+
+		let res = Announce::decode_version(&mut buf.as_slice(), Version::CURRENT);
+		assert!(matches!(res, Err(DecodeError::BoundsExceeded)));
+	}
+
+	#[test]
+	fn announce_please_versions() {
+		let please = AnnouncePlease {
+			prefix: Path::default().push("room"),
+			pattern: Path::default().push("*").push("camera"),
+			depth: Some(2),
+		};
+
+		for version in Version::SUPPORTED {
+			let mut buf = Vec::new();
+			please.encode_version(&mut buf, version);
+
+			let mut r = buf.as_slice();
+			let decoded = AnnouncePlease::decode_version(&mut r, version).unwrap();
+			assert!(r.is_empty());
+			assert_eq!(decoded.prefix, please.prefix);
+
+			// Older versions can't express a filter.
+			if version >= Version::FORK_07 {
+				assert_eq!(decoded.pattern, please.pattern);
+				assert_eq!(decoded.depth, Some(2));
+			} else {
+				assert!(decoded.pattern.is_empty());
+				assert_eq!(decoded.depth, None);
+			}
+		}
+	}
+
+	#[test]
+	fn announce_please_depth_max() {
+		let please = AnnouncePlease {
+			prefix: Path::default(),
+			pattern: Path::default(),
+			depth: Some(u64::MAX),
+		};
+
+		let mut buf = Vec::new();
+		please.encode_version(&mut buf, Version::CURRENT);
+
+		let decoded = AnnouncePlease::decode_version(&mut buf.as_slice(), Version::CURRENT).unwrap();
+		assert_eq!(decoded.depth, Some(VarInt::MAX.into_inner() - 1));
+	}
+}
@@ -52,10 +52,16 @@ impl Encode for GroupOrder {
 	}
 }
 
+/// Sent by the publisher when one or more groups will not be delivered.
 #[derive(Clone, Debug)]
 pub struct GroupDrop {
+	// The first group sequence that was dropped.
 	pub sequence: u64,
+
+	// The number of additional groups that were dropped after `sequence`.
 	pub count: u64,
+
+	// The error code.
 	pub code: u32,
 }
 
@@ -7,10 +7,12 @@ mod fetch;
 mod frame;
 mod group;
 mod info;
+mod role;
 mod session;
 mod setup;
 mod stream;
 mod subscribe;
+mod token;
 mod versions;
 
 pub use announce::*;
@@ -19,8 +21,10 @@ pub use fetch::*;
 pub use frame::*;
 pub use group::*;
 pub use info::*;
+pub use role::*;
 pub use session::*;
 pub use setup::*;
 pub use stream::*;
 pub use subscribe::*;
+pub use token::*;
 pub use versions::*;
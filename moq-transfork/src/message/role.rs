@@ -0,0 +1,88 @@
+use crate::coding::*;
+
+use super::Extension;
+
+/// Sent as a setup extension to indicate which direction(s) an endpoint supports.
+///
+/// A missing extension is treated as [Role::Both].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Role {
+	/// Only publishes tracks; the peer can't publish any.
+	Publisher,
+
+	/// Only subscribes to tracks; the peer can't subscribe to any.
+	Subscriber,
+
+	/// Publishes and subscribes.
+	#[default]
+	Both,
+}
+
+impl Role {
+	/// Returns true if this endpoint can publish.
+	pub fn is_publisher(&self) -> bool {
+		matches!(self, Self::Publisher | Self::Both)
+	}
+
+	/// Returns true if this endpoint can subscribe.
+	pub fn is_subscriber(&self) -> bool {
+		matches!(self, Self::Subscriber | Self::Both)
+	}
+
+	/// Combine our role with the peer's, returning what we're actually allowed to do, if anything.
+	pub fn downgrade(&self, peer: Role) -> Option<Role> {
+		let publisher = self.is_publisher() && peer.is_subscriber();
+		let subscriber = self.is_subscriber() && peer.is_publisher();
+
+		match (publisher, subscriber) {
+			(true, true) => Some(Self::Both),
+			(true, false) => Some(Self::Publisher),
+			(false, true) => Some(Self::Subscriber),
+			(false, false) => None,
+		}
+	}
+}
+
+impl Decode for Role {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		match u64::decode(r)? {
+			1 => Ok(Self::Publisher),
+			2 => Ok(Self::Subscriber),
+			3 => Ok(Self::Both),
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
+
+impl Encode for Role {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		let v: u64 = match self {
+			Self::Publisher => 1,
+			Self::Subscriber => 2,
+			Self::Both => 3,
+		};
+		v.encode(w)
+	}
+}
+
+impl Extension for Role {
+	fn id() -> u64 {
+		0x00
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn downgrade() {
+		assert_eq!(Role::Both.downgrade(Role::Both), Some(Role::Both));
+		assert_eq!(Role::Both.downgrade(Role::Subscriber), Some(Role::Publisher));
+		assert_eq!(Role::Both.downgrade(Role::Publisher), Some(Role::Subscriber));
+		assert_eq!(Role::Publisher.downgrade(Role::Both), Some(Role::Publisher));
+		assert_eq!(Role::Publisher.downgrade(Role::Subscriber), Some(Role::Publisher));
+		assert_eq!(Role::Publisher.downgrade(Role::Publisher), None);
+		assert_eq!(Role::Subscriber.downgrade(Role::Subscriber), None);
+	}
+}
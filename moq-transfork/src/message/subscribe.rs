@@ -1,6 +1,6 @@
 use crate::{
 	coding::{Decode, DecodeError, Encode},
-	message::group,
+	message::{group, Version, Versioned},
 	Path,
 };
 
@@ -16,10 +16,12 @@ pub struct Subscribe {
 	pub group_order: group::GroupOrder,
 	pub group_min: Option<u64>,
 	pub group_max: Option<u64>,
+
+	pub drain: Drain,
 }
 
-impl Decode for Subscribe {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+impl Versioned for Subscribe {
+	fn decode_version<R: bytes::Buf>(r: &mut R, version: Version) -> Result<Self, DecodeError> {
 		let id = u64::decode(r)?;
 		let path = Path::decode(r)?;
 		let priority = i8::decode(r)?;
@@ -34,6 +36,11 @@ impl Decode for Subscribe {
 			n => Some(n - 1),
 		};
 
+		let drain = match version >= Version::FORK_06 {
+			true => Drain::decode(r)?,
+			false => Drain::default(),
+		};
+
 		Ok(Self {
 			id,
 			path,
@@ -42,12 +49,12 @@ impl Decode for Subscribe {
 			group_order,
 			group_min,
 			group_max,
+
+			drain,
 		})
 	}
-}
 
-impl Encode for Subscribe {
-	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
 		self.id.encode(w);
 		self.path.encode(w);
 		self.priority.encode(w);
@@ -55,21 +62,78 @@ impl Encode for Subscribe {
 		self.group_order.encode(w);
 		self.group_min.map(|v| v + 1).unwrap_or(0).encode(w);
 		self.group_max.map(|v| v + 1).unwrap_or(0).encode(w);
+
+		if version >= Version::FORK_06 {
+			self.drain.encode(w);
+		}
+	}
+}
+
+impl Decode for Subscribe {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Self::decode_version(r, Version::CURRENT)
+	}
+}
+
+impl Encode for Subscribe {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.encode_version(w, Version::CURRENT)
+	}
+}
+
+/// What the publisher should do with any groups in flight when a subscription is cancelled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Drain {
+	/// Finish every group that has already started, but don't start any new groups.
+	#[default]
+	Finish,
+
+	/// Only finish the current group (the newest for [group::GroupOrder::Desc]), resetting the rest.
+	Current,
+
+	/// Reset every group immediately.
+	Reset,
+}
+
+impl Decode for Drain {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		match u64::decode(r)? {
+			0 => Ok(Self::Finish),
+			1 => Ok(Self::Current),
+			2 => Ok(Self::Reset),
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
+
+impl Encode for Drain {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		let v: u64 = match self {
+			Self::Finish => 0,
+			Self::Current => 1,
+			Self::Reset => 2,
+		};
+		v.encode(w)
 	}
 }
 
+/// Sent by the subscriber to change an active subscription.
 #[derive(Clone, Debug)]
 pub struct SubscribeUpdate {
-	pub priority: u64,
+	pub priority: i8,
 
 	pub group_order: group::GroupOrder,
 	pub group_min: Option<u64>,
 	pub group_max: Option<u64>,
 }
 
-impl Decode for SubscribeUpdate {
-	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
-		let priority = u64::decode(r)?;
+impl Versioned for SubscribeUpdate {
+	fn decode_version<R: bytes::Buf>(r: &mut R, version: Version) -> Result<Self, DecodeError> {
+		let priority = match version >= Version::FORK_05 {
+			true => i8::decode(r)?,
+			// FORK_04 used a varint, so we use the same offset as i8.
+			false => (u64::decode(r)?.min(u8::MAX as u64) as i16 - 128) as i8,
+		};
 		let group_order = group::GroupOrder::decode(r)?;
 		let group_min = match u64::decode(r)? {
 			0 => None,
@@ -87,13 +151,86 @@ impl Decode for SubscribeUpdate {
 			group_max,
 		})
 	}
-}
 
-impl Encode for SubscribeUpdate {
-	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
-		self.priority.encode(w);
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version) {
+		match version >= Version::FORK_05 {
+			true => self.priority.encode(w),
+			false => ((self.priority as i16 + 128) as u64).encode(w),
+		}
 		self.group_order.encode(w);
 		self.group_min.map(|v| v + 1).unwrap_or(0).encode(w);
 		self.group_max.map(|v| v + 1).unwrap_or(0).encode(w);
 	}
 }
+
+impl Decode for SubscribeUpdate {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Self::decode_version(r, Version::CURRENT)
+	}
+}
+
+impl Encode for SubscribeUpdate {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.encode_version(w, Version::CURRENT)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn update_versions() {
+		let update = SubscribeUpdate {
+			priority: -3,
+			group_order: group::GroupOrder::Asc,
+			group_min: Some(4),
+			group_max: None,
+		};
+
+		for version in Version::SUPPORTED {
+			let mut buf = Vec::new();
+			update.encode_version(&mut buf, version);
+
+			let decoded = SubscribeUpdate::decode_version(&mut buf.as_slice(), version).unwrap();
+			assert_eq!(decoded.priority, -3);
+			assert_eq!(decoded.group_min, Some(4));
+		}
+
+		// The priority is a varint in FORK_04, so it's two bytes instead of one.
+		let mut old = Vec::new();
+		update.encode_version(&mut old, Version::FORK_04);
+		let mut new = Vec::new();
+		update.encode_version(&mut new, Version::FORK_05);
+		assert_eq!(old.len(), new.len() + 1);
+	}
+
+	#[test]
+	fn subscribe_versions() {
+		let subscribe = Subscribe {
+			id: 1,
+			path: Path::default().push("test"),
+			priority: 0,
+			group_order: group::GroupOrder::Desc,
+			group_min: None,
+			group_max: Some(7),
+			drain: Drain::Reset,
+		};
+
+		for version in Version::SUPPORTED {
+			let mut buf = Vec::new();
+			subscribe.encode_version(&mut buf, version);
+
+			let mut r = buf.as_slice();
+			let decoded = Subscribe::decode_version(&mut r, version).unwrap();
+			assert!(r.is_empty());
+			assert_eq!(decoded.group_max, Some(7));
+
+			// Older versions can't express a drain policy.
+			match version >= Version::FORK_06 {
+				true => assert_eq!(decoded.drain, Drain::Reset),
+				false => assert_eq!(decoded.drain, Drain::Finish),
+			}
+		}
+	}
+}
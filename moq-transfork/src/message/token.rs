@@ -0,0 +1,27 @@
+use crate::coding::*;
+
+use super::Extension;
+
+/// Sent as a setup extension by the client to authenticate, ex. a JWT.
+///
+/// Useful for transports without a URL, such as raw QUIC, where the token can't be provided as a query parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token(pub String);
+
+impl Decode for Token {
+	fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self(String::decode(r)?))
+	}
+}
+
+impl Encode for Token {
+	fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+		self.0.encode(w)
+	}
+}
+
+impl Extension for Token {
+	fn id() -> u64 {
+		0x02
+	}
+}
@@ -3,6 +3,9 @@ use crate::coding::*;
 use std::{fmt, ops::Deref};
 
 /// A version number negotiated during the setup.
+///
+/// Versions are ordered, so gate each wire change on the version that introduced it (ex. `version >= Version::FORK_05`).
+/// That way newer versions inherit the change without updating every match.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Version(u64);
 
@@ -37,7 +40,28 @@ impl Version {
 	/// Unpublished: <https://kixelated.github.io/moq-drafts/draft-lcurley-moq-transfork.html>
 	pub const FORK_04: Version = Version(0xff0bad04);
 
-	pub const CURRENT: Version = Version::FORK_04;
+	/// Unpublished: FORK_04 but SUBSCRIBE_UPDATE encodes the priority as an i8, like SUBSCRIBE.
+	pub const FORK_05: Version = Version(0xff0bad05);
+
+	/// Unpublished: FORK_05 but SUBSCRIBE includes a drain policy.
+	pub const FORK_06: Version = Version(0xff0bad06);
+
+	/// Unpublished: FORK_06 but ANNOUNCE_PLEASE includes a pattern and maximum depth.
+	pub const FORK_07: Version = Version(0xff0bad07);
+
+	/// Unpublished: FORK_07 but ANNOUNCE includes metadata for active tracks.
+	pub const FORK_08: Version = Version(0xff0bad08);
+
+	pub const CURRENT: Version = Version::FORK_08;
+
+	/// Every version we can speak, in preferred order.
+	pub const SUPPORTED: [Version; 5] = [
+		Version::FORK_08,
+		Version::FORK_07,
+		Version::FORK_06,
+		Version::FORK_05,
+		Version::FORK_04,
+	];
 }
 
 impl From<u64> for Version {
@@ -102,6 +126,13 @@ impl Encode for Versions {
 	}
 }
 
+impl Versions {
+	/// Return the first version in our preferred order that is also supported by the other side.
+	pub fn select(&self, other: &Versions) -> Option<Version> {
+		self.0.iter().find(|v| other.contains(v)).copied()
+	}
+}
+
 impl Deref for Versions {
 	type Target = Vec<Version>;
 
@@ -127,3 +158,35 @@ impl fmt::Debug for Versions {
 		f.debug_list().entries(self.0.iter()).finish()
 	}
 }
+
+/// A message with an encoding that depends on the negotiated [Version].
+pub trait Versioned: Sized {
+	fn encode_version<W: bytes::BufMut>(&self, w: &mut W, version: Version);
+	fn decode_version<R: bytes::Buf>(r: &mut R, version: Version) -> Result<Self, DecodeError>;
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn select() {
+		let ours: Versions = Version::SUPPORTED.into();
+
+		let theirs: Versions = [Version::FORK_03, Version::FORK_04].into();
+		assert_eq!(ours.select(&theirs), Some(Version::FORK_04));
+
+		let theirs: Versions = [Version::FORK_04, Version::FORK_05].into();
+		assert_eq!(ours.select(&theirs), Some(Version::FORK_05));
+
+		let theirs: Versions = [Version::FORK_03].into();
+		assert_eq!(ours.select(&theirs), None);
+	}
+
+	#[test]
+	fn order() {
+		// Newer versions compare greater, so wire changes can be gated with a comparison.
+		assert!(Version::SUPPORTED.windows(2).all(|pair| pair[0] > pair[1]));
+		assert_eq!(Version::SUPPORTED[0], Version::CURRENT);
+	}
+}
@@ -1,13 +1,20 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use bytes::Bytes;
 use tokio::sync::watch;
 
 use super::Path;
 
+// The minimum number of changes to keep, so consumers that fall slightly behind don't need to resync.
+// Otherwise we keep one change per active path, so resyncing is amortized over the changes we skipped.
+const MIN_CHANGES: usize = 1024;
+
 /// The suffix of each announced track.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Announced {
-	// Indicates the track is active.
-	Active(Path),
+	// Indicates the track is active, along with any metadata provided by the publisher.
+	// NOTE: This is repeated if the metadata changes.
+	Active(Path, Bytes),
 
 	// Indicates the track is no longer active.
 	Ended(Path),
@@ -16,10 +23,113 @@ pub enum Announced {
 	Live,
 }
 
+/// Which announced paths to return, and the prefix to strip from each of them.
+///
+/// A `*` part matches any single part, so `room/*/camera` returns `alice/camera` but not `alice/screen`.
+/// Any parts after the pattern are returned too, unless limited with [Self::depth].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnouncedFilter {
+	/// The literal prefix, stripped from each path.
+	pub prefix: Path,
+
+	/// Each suffix must start with this pattern, where a `*` part matches any single part.
+	pub pattern: Path,
+
+	/// The maximum number of parts in each suffix, or None for any depth.
+	pub depth: Option<usize>,
+}
+
+impl AnnouncedFilter {
+	/// A part that matches any single part.
+	///
+	/// There's no escaping, so a path containing a literal `*` part can only be matched by a wildcard.
+	pub const WILDCARD: &'static str = "*";
+
+	/// Match any paths starting with the given path, which may contain wildcards.
+	///
+	/// The prefix is everything before the first wildcard, and the rest is the pattern.
+	/// Any `*` part is treated as [Self::WILDCARD], so it's not possible to filter for a literal `*` part.
+	pub fn new(path: Path) -> Self {
+		let split = path
+			.iter()
+			.position(|part| part == Self::WILDCARD)
+			.unwrap_or(path.len());
+
+		let mut prefix = path;
+		let pattern = prefix.split_off(split).into();
+
+		Self {
+			prefix,
+			pattern,
+			depth: None,
+		}
+	}
+
+	/// Only return suffixes with at most this many parts.
+	pub fn depth(mut self, depth: usize) -> Self {
+		self.depth = Some(depth);
+		self
+	}
+
+	/// Returns the suffix if the path matches.
+	pub fn matches(&self, path: &Path) -> Option<Path> {
+		let suffix = path.clone().strip_prefix(&self.prefix)?;
+
+		if suffix.len() < self.pattern.len() || self.depth.is_some_and(|depth| suffix.len() > depth) {
+			return None;
+		}
+
+		let matched = self
+			.pattern
+			.iter()
+			.zip(suffix.iter())
+			.all(|(pattern, part)| pattern == Self::WILDCARD || pattern == part);
+
+		matched.then_some(suffix)
+	}
+}
+
+impl From<Path> for AnnouncedFilter {
+	fn from(path: Path) -> Self {
+		Self::new(path)
+	}
+}
+
 #[derive(Default)]
 struct State {
-	active: BTreeSet<Path>,
+	// Each active path and its metadata.
+	active: BTreeMap<Path, Bytes>,
 	live: bool,
+
+	// A log of recent changes, where true means the path became active or its metadata changed.
+	changes: VecDeque<(Path, bool)>,
+
+	// The epoch of the first change in the log, incremented as old changes are discarded.
+	offset: u64,
+}
+
+impl State {
+	// The epoch of the next change.
+	fn epoch(&self) -> u64 {
+		self.offset + self.changes.len() as u64
+	}
+
+	fn change(&mut self, path: Path, active: bool) {
+		self.changes.push_back((path, active));
+
+		while self.changes.len() > self.active.len().max(MIN_CHANGES) {
+			self.changes.pop_front();
+			self.offset += 1;
+		}
+	}
+
+	// Iterate over the active paths with the given prefix, which are contiguous because paths are sorted.
+	fn active_prefix<'a>(&'a self, prefix: &'a Path) -> impl Iterator<Item = &'a Path> {
+		self.active
+			.range(prefix.clone()..)
+			.map(|(path, _)| path)
+			.take_while(move |path| path.has_prefix(prefix))
+	}
 }
 
 /// Announces tracks to consumers over the network.
@@ -35,16 +145,44 @@ impl AnnouncedProducer {
 
 	/// Announce a track, returning true if it's new.
 	pub fn announce(&mut self, path: Path) -> bool {
-		self.state.send_if_modified(|state| state.active.insert(path))
+		self.announce_with(path, Bytes::new())
+	}
+
+	/// Announce a track with some metadata, returning true if it's new or the metadata changed.
+	///
+	/// The metadata is opaque and sent with each announcement, so keep it small (ex. a display name or catalog hash).
+	/// Peers reject metadata larger than [crate::message::Announce::MAX_METADATA].
+	pub fn announce_with(&mut self, path: Path, metadata: Bytes) -> bool {
+		self.state.send_if_modified(|state| {
+			if state.active.get(&path) == Some(&metadata) {
+				return false;
+			}
+
+			state.active.insert(path.clone(), metadata);
+			state.change(path, true);
+			true
+		})
 	}
 
 	/// Stop announcing a track, returning true if it was active.
 	pub fn unannounce(&mut self, path: &Path) -> bool {
-		self.state.send_if_modified(|state| state.active.remove(path))
+		self.state.send_if_modified(|state| {
+			if state.active.remove(path).is_none() {
+				return false;
+			}
+
+			state.change(path.clone(), false);
+			true
+		})
 	}
 
 	pub fn contains(&self, path: &Path) -> bool {
-		self.state.borrow().active.contains(path)
+		self.state.borrow().active.contains_key(path)
+	}
+
+	/// Returns the metadata for an active track.
+	pub fn metadata(&self, path: &Path) -> Option<Bytes> {
+		self.state.borrow().active.get(path).cloned()
 	}
 
 	/// Indicate that we're caught up to the current state of the world.
@@ -71,13 +209,24 @@ impl AnnouncedProducer {
 
 	/// Subscribe to all announced tracks based on a prefix, including those already active.
 	pub fn subscribe_prefix(&self, prefix: Path) -> AnnouncedConsumer {
-		AnnouncedConsumer::new(self.state.subscribe(), prefix)
+		self.subscribe_filter(AnnouncedFilter {
+			prefix,
+			..Default::default()
+		})
+	}
+
+	/// Subscribe to all announced tracks matching a filter, including those already active.
+	pub fn subscribe_filter(&self, filter: AnnouncedFilter) -> AnnouncedConsumer {
+		AnnouncedConsumer::new(self.state.subscribe(), filter)
 	}
 
 	/// Clear all announced tracks.
 	pub fn reset(&mut self) {
 		self.state.send_modify(|state| {
-			state.active.clear();
+			for (path, _) in std::mem::take(&mut state.active) {
+				state.change(path, false);
+			}
+
 			state.live = false;
 		});
 	}
@@ -87,53 +236,74 @@ impl AnnouncedProducer {
 	}
 }
 
-/// Consumes announced tracks over the network matching an optional prefix.
+/// Consumes announced tracks over the network matching an optional filter.
+///
+/// Each consumer starts with a snapshot of the active paths, then reads the log of changes since.
+/// A path that is announced and unannounced before the consumer catches up is skipped entirely.
 #[derive(Clone)]
 pub struct AnnouncedConsumer {
 	// The official list of active paths.
 	state: watch::Receiver<State>,
 
-	// A set of updates that we haven't consumed yet.
-	active: BTreeSet<Path>,
+	// The paths that we've returned as active, and their metadata.
+	active: BTreeMap<Path, Bytes>,
+
+	// Paths that may have been added, updated, or removed since we last returned them.
+	added: BTreeSet<Path>,
+	removed: BTreeSet<Path>,
 
-	// Only consume paths with this prefix.
-	prefix: Path,
+	// The epoch of the next change to read.
+	// NOTE: None until we've taken a snapshot.
+	epoch: Option<u64>,
+
+	// Only consume paths matching this filter.
+	filter: AnnouncedFilter,
 
 	// Indicates if the publisher is still active.
 	live: bool,
 }
 
 impl AnnouncedConsumer {
-	fn new(state: watch::Receiver<State>, prefix: Path) -> Self {
+	fn new(state: watch::Receiver<State>, filter: AnnouncedFilter) -> Self {
 		Self {
 			state,
-			active: BTreeSet::new(),
-			prefix,
+			active: BTreeMap::new(),
+			added: BTreeSet::new(),
+			removed: BTreeSet::new(),
+			epoch: None,
+			filter,
 			live: false,
 		}
 	}
 
 	/// Returns the suffix of the next announced track received already.
 	fn try_next(&mut self) -> Option<Announced> {
-		let state = self.state.borrow();
+		self.update();
 
-		// TODO this absolutely need to be optimized one day.
-		while let Some(removed) = self.active.difference(&state.active).next().cloned() {
-			self.active.remove(&removed);
-			if let Some(suffix) = removed.strip_prefix(&self.prefix) {
+		// Return any removals first, then any additions.
+		while let Some(path) = self.removed.pop_first() {
+			if self.active.remove(&path).is_some() {
+				let suffix = path.strip_prefix(&self.filter.prefix).unwrap();
 				return Some(Announced::Ended(suffix));
 			}
 		}
 
-		while let Some(added) = state.active.difference(&self.active).next().cloned() {
-			self.active.insert(added.clone());
-			if let Some(suffix) = added.strip_prefix(&self.prefix) {
-				return Some(Announced::Active(suffix));
+		while let Some(path) = self.added.pop_first() {
+			// Use the latest metadata, skipping the path if it was removed since we last updated.
+			let metadata = match self.state.borrow().active.get(&path) {
+				Some(metadata) => metadata.clone(),
+				None => continue,
+			};
+
+			if self.active.get(&path) != Some(&metadata) {
+				self.active.insert(path.clone(), metadata.clone());
+				let suffix = path.strip_prefix(&self.filter.prefix).unwrap();
+				return Some(Announced::Active(suffix, metadata));
 			}
 		}
 
 		// Return the live marker if needed.
-		if state.live && !self.live {
+		if self.state.borrow().live && !self.live {
 			self.live = true;
 			return Some(Announced::Live);
 		}
@@ -141,6 +311,47 @@ impl AnnouncedConsumer {
 		None
 	}
 
+	// Read any changes since the last update, in O(changes) unless we fell too far behind.
+	fn update(&mut self) {
+		let state = self.state.borrow();
+
+		match self.epoch {
+			Some(epoch) if epoch >= state.offset => {
+				let start = (epoch - state.offset) as usize;
+				for (path, active) in state.changes.range(start..) {
+					if self.filter.matches(path).is_none() {
+						continue;
+					}
+
+					// Only the most recent change matters, so paths that flicker are skipped.
+					if *active {
+						self.removed.remove(path);
+						self.added.insert(path.clone());
+					} else {
+						self.added.remove(path);
+						self.removed.insert(path.clone());
+					}
+				}
+			}
+			_ => {
+				// Take a snapshot, either because we just joined or the changes we need were discarded.
+				self.added.clear();
+				self.removed = self.active.keys().cloned().collect();
+
+				for path in state.active_prefix(&self.filter.prefix) {
+					if self.filter.matches(path).is_none() {
+						continue;
+					}
+
+					self.removed.remove(path);
+					self.added.insert(path.clone());
+				}
+			}
+		}
+
+		self.epoch = Some(state.epoch());
+	}
+
 	/// Returns the suffix of the next announced track.
 	pub async fn next(&mut self) -> Option<Announced> {
 		// NOTE: This just checks if the producer has been dropped.
@@ -158,8 +369,9 @@ impl AnnouncedConsumer {
 		}
 
 		// The publisher is closed, so return `Ended` for all active paths.
-		while let Some(removed) = self.active.pop_first() {
-			if let Some(suffix) = removed.strip_prefix(&self.prefix) {
+
+		while let Some((removed, _)) = self.active.pop_first() {
+			if let Some(suffix) = removed.strip_prefix(&self.filter.prefix) {
 				return Some(Announced::Ended(suffix));
 			}
 		}
@@ -169,7 +381,12 @@ impl AnnouncedConsumer {
 
 	/// Returns the prefix in use.
 	pub fn prefix(&self) -> &Path {
-		&self.prefix
+		&self.filter.prefix
+	}
+
+	/// Returns the filter in use.
+	pub fn filter(&self) -> &AnnouncedFilter {
+		&self.filter
 	}
 }
 
@@ -193,7 +410,7 @@ mod test {
 		assert!(producer.contains(&path));
 
 		let announced = consumer.next().now_or_never().unwrap().unwrap();
-		assert!(matches!(announced, Announced::Active(active) if active == path));
+		assert!(matches!(announced, Announced::Active(active, _) if active == path));
 
 		assert!(producer.unannounce(&path));
 		assert!(!producer.contains(&path));
@@ -224,7 +441,7 @@ mod test {
 		while !paths.is_empty() {
 			let res = consumer.next().now_or_never().unwrap().unwrap();
 			match res {
-				Announced::Active(active) => assert!(paths.remove(&active)),
+				Announced::Active(active, _) => assert!(paths.remove(&active)),
 				_ => panic!("unexpected announcement: {:?}", res),
 			}
 		}
@@ -254,7 +471,7 @@ mod test {
 		while !paths.is_empty() {
 			let res = consumer.next().now_or_never().unwrap().unwrap();
 			match res {
-				Announced::Active(active) => assert!(paths.remove(&active)),
+				Announced::Active(active, _) => assert!(paths.remove(&active)),
 				_ => panic!("unexpected announcement: {:?}", res),
 			}
 		}
@@ -284,7 +501,7 @@ mod test {
 		while !expected.is_empty() {
 			let res = consumer.next().now_or_never().unwrap().unwrap();
 			match res {
-				Announced::Active(active) => assert!(expected.remove(&active)),
+				Announced::Active(active, _) => assert!(expected.remove(&active)),
 				_ => panic!("unexpected announcement: {:?}", res),
 			}
 		}
@@ -310,7 +527,7 @@ mod test {
 		assert!(producer.announce(path3.clone()));
 
 		let res = match consumer.next().now_or_never().unwrap().unwrap() {
-			Announced::Active(ended) if ended == suffix1 || ended == suffix2 => ended,
+			Announced::Active(ended, _) if ended == suffix1 || ended == suffix2 => ended,
 			res => panic!("unexpected announcement: {:?}", res),
 		};
 
@@ -355,12 +572,12 @@ mod test {
 		producer.announce(path1.clone());
 		assert_eq!(
 			consumer.next().now_or_never().unwrap(),
-			Some(Announced::Active(path1.clone()))
+			Some(Announced::Active(path1.clone(), Bytes::new()))
 		);
 		producer.announce(path2.clone());
 		assert_eq!(
 			consumer.next().now_or_never().unwrap(),
-			Some(Announced::Active(path2.clone()))
+			Some(Announced::Active(path2.clone(), Bytes::new()))
 		);
 
 		// Don't consume path3 before dropping.
@@ -397,11 +614,11 @@ mod test {
 
 		assert_eq!(
 			consumer.next().now_or_never().unwrap(),
-			Some(Announced::Active(path1.clone()))
+			Some(Announced::Active(path1.clone(), Bytes::new()))
 		);
 		assert_eq!(
 			consumer.next().now_or_never().unwrap(),
-			Some(Announced::Active(path2.clone()))
+			Some(Announced::Active(path2.clone(), Bytes::new()))
 		);
 		// We actually get live after path2 because we were slow to consume.
 		assert_eq!(consumer.next().now_or_never().unwrap(), Some(Announced::Live));
@@ -411,11 +628,173 @@ mod test {
 
 		assert_eq!(
 			consumer.next().now_or_never().unwrap(),
-			Some(Announced::Active(path3.clone()))
+			Some(Announced::Active(path3.clone(), Bytes::new()))
+		);
+		assert_eq!(consumer.next().now_or_never(), None);
+	}
+
+	#[test]
+	fn resync() {
+		let mut producer = AnnouncedProducer::new();
+		let mut consumer = producer.subscribe();
+
+		let path1 = Path::default().push("a");
+		let path2 = Path::default().push("b");
+
+		producer.announce(path1.clone());
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Active(path1.clone(), Bytes::new()))
+		);
+
+		// Churn enough that the changes we need are discarded.
+		for i in 0..MIN_CHANGES {
+			let path = Path::default().push("c").push(i);
+			producer.announce(path.clone());
+			producer.unannounce(&path);
+		}
+
+		producer.unannounce(&path1);
+		producer.announce(path2.clone());
+
+		assert!(producer.state.borrow().changes.len() <= MIN_CHANGES);
+
+		// We fall back to a snapshot, which skips the churn entirely.
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Ended(path1.clone()))
+		);
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Active(path2.clone(), Bytes::new()))
 		);
 		assert_eq!(consumer.next().now_or_never(), None);
 	}
 
+	#[test]
+	fn reset() {
+		let mut producer = AnnouncedProducer::new();
+		let mut consumer = producer.subscribe();
+
+		let path = Path::default().push("a").push("b");
+
+		producer.announce(path.clone());
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Active(path.clone(), Bytes::new()))
+		);
+
+		producer.reset();
+		assert!(producer.is_empty());
+
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Ended(path.clone()))
+		);
+		assert_eq!(consumer.next().now_or_never(), None);
+	}
+
+	#[test]
+	fn metadata() {
+		let mut producer = AnnouncedProducer::new();
+		let mut consumer = producer.subscribe();
+
+		let path = Path::default().push("alice");
+		let alice = Bytes::from_static(b"Alice");
+
+		assert!(producer.announce_with(path.clone(), alice.clone()));
+		assert!(!producer.announce_with(path.clone(), alice.clone()));
+		assert_eq!(producer.metadata(&path), Some(alice.clone()));
+
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Active(path.clone(), alice))
+		);
+		assert_eq!(consumer.next().now_or_never(), None);
+
+		// Changing the metadata announces the path again, skipping any stale values.
+		assert!(producer.announce_with(path.clone(), Bytes::from_static(b"Alice (away)")));
+		assert!(producer.announce_with(path.clone(), Bytes::from_static(b"Alice (back)")));
+
+		assert_eq!(
+			consumer.next().now_or_never().unwrap(),
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"Alice (back)")))
+		);
+		assert_eq!(consumer.next().now_or_never(), None);
+
+		// New consumers get the latest metadata.
+		let mut late = producer.subscribe();
+		assert_eq!(
+			late.next().now_or_never().unwrap(),
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"Alice (back)")))
+		);
+	}
+
+	#[test]
+	fn filter() {
+		let mut producer = AnnouncedProducer::new();
+
+		let room = Path::default().push("room");
+		let filter = AnnouncedFilter::new(room.clone().push("*").push("camera"));
+		assert_eq!(filter.prefix, room);
+		assert_eq!(filter.pattern, Path::default().push("*").push("camera"));
+
+		let mut cameras = producer.subscribe_filter(filter.depth(2));
+		let mut participants = producer.subscribe_filter(AnnouncedFilter::new(room.clone()).depth(1));
+
+		producer.announce(room.clone().push("alice"));
+		producer.announce(room.clone().push("alice").push("camera"));
+		producer.announce(room.clone().push("alice").push("camera").push("hd"));
+		producer.announce(room.clone().push("alice").push("screen"));
+		producer.announce(room.clone().push("bob"));
+		producer.announce(room.clone().push("bob").push("camera"));
+		producer.announce(Path::default().push("other").push("camera"));
+
+		assert_eq!(
+			cameras.next().now_or_never().unwrap(),
+			Some(Announced::Active(
+				Path::default().push("alice").push("camera"),
+				Bytes::new()
+			))
+		);
+		assert_eq!(
+			cameras.next().now_or_never().unwrap(),
+			Some(Announced::Active(
+				Path::default().push("bob").push("camera"),
+				Bytes::new()
+			))
+		);
+		assert_eq!(cameras.next().now_or_never(), None);
+
+		assert_eq!(
+			participants.next().now_or_never().unwrap(),
+			Some(Announced::Active(Path::default().push("alice"), Bytes::new()))
+		);
+		assert_eq!(
+			participants.next().now_or_never().unwrap(),
+			Some(Announced::Active(Path::default().push("bob"), Bytes::new()))
+		);
+		assert_eq!(participants.next().now_or_never(), None);
+
+		// Late consumers get the same snapshot.
+		let mut late = producer.subscribe_filter(AnnouncedFilter::new(room.clone().push("*").push("camera")).depth(2));
+		producer.unannounce(&room.clone().push("bob").push("camera"));
+
+		assert_eq!(
+			late.next().now_or_never().unwrap(),
+			Some(Announced::Active(
+				Path::default().push("alice").push("camera"),
+				Bytes::new()
+			))
+		);
+		assert_eq!(late.next().now_or_never(), None);
+
+		assert_eq!(
+			cameras.next().now_or_never().unwrap(),
+			Some(Announced::Ended(Path::default().push("bob").push("camera")))
+		);
+	}
+
 	#[tokio::test]
 	async fn wakeup() {
 		tokio::time::pause();
@@ -442,13 +821,13 @@ mod test {
 		});
 
 		let res = match consumer.next().await.unwrap() {
-			Announced::Active(active) if active == path1 || active == path2 => active,
+			Announced::Active(active, _) if active == path1 || active == path2 => active,
 			res => panic!("unexpected announcement: {:?}", res),
 		};
 
 		match consumer.next().await.unwrap() {
-			Announced::Active(dup) if dup == res => panic!("duplicate announcement: {:?}", dup),
-			Announced::Active(active) if active == path1 || active == path2 => active,
+			Announced::Active(dup, _) if dup == res => panic!("duplicate announcement: {:?}", dup),
+			Announced::Active(active, _) if active == path1 || active == path2 => active,
 			res => panic!("unexpected announcement: {:?}", res),
 		};
 
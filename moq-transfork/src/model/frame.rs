@@ -29,6 +29,9 @@ struct FrameState {
 	// The chunks that has been written thus far
 	chunks: Vec<Bytes>,
 
+	// The sum of each chunk's size, which can't exceed the frame size.
+	written: usize,
+
 	// Set when the writer or all readers are dropped.
 	closed: Result<(), Error>,
 }
@@ -37,6 +40,7 @@ impl Default for FrameState {
 	fn default() -> Self {
 		Self {
 			chunks: Vec::new(),
+			written: 0,
 			closed: Ok(()),
 		}
 	}
@@ -66,8 +70,37 @@ impl FrameProducer {
 		Self { state, info }
 	}
 
+	/// Write a chunk of the frame.
+	///
+	/// The chunk is discarded if the frame is closed, and the frame is closed with [Error::WrongSize] if it would exceed the upfront size.
 	pub fn write<B: Into<Bytes>>(&mut self, chunk: B) {
-		self.state.send_modify(|state| state.chunks.push(chunk.into()));
+		self.write_chunks([chunk.into()]);
+	}
+
+	/// Write multiple chunks of the frame, waking any consumers only once.
+	pub fn write_chunks<I: IntoIterator<Item = Bytes>>(&mut self, chunks: I) {
+		let size = self.info.size;
+
+		self.state.send_if_modified(|state| {
+			if state.closed.is_err() {
+				return false;
+			}
+
+			let mut modified = false;
+
+			for chunk in chunks {
+				if state.written + chunk.len() > size {
+					state.closed = Err(Error::WrongSize);
+					return true;
+				}
+
+				state.written += chunk.len();
+				state.chunks.push(chunk);
+				modified = true;
+			}
+
+			modified
+		});
 	}
 
 	/// Close the stream with an error.
@@ -132,6 +165,29 @@ impl FrameConsumer {
 		}
 	}
 
+	// Return every chunk that has been written but not read yet, waiting for at least one.
+	//
+	// The chunks are reference counted, so only the handles are cloned, and we wake up once per batch instead of once per chunk.
+	pub async fn read_chunks(&mut self) -> Result<Option<Vec<Bytes>>, Error> {
+		loop {
+			{
+				let state = self.state.borrow_and_update();
+
+				if self.index < state.chunks.len() {
+					let chunks = state.chunks[self.index..].to_vec();
+					self.index = state.chunks.len();
+					return Ok(Some(chunks));
+				}
+
+				state.closed.clone()?;
+			}
+
+			if self.state.changed().await.is_err() {
+				return Ok(None);
+			}
+		}
+	}
+
 	// Return all of the remaining chunks concatenated together.
 	pub async fn read_all(&mut self) -> Result<Bytes, Error> {
 		// Wait until the writer is done before even attempting to read.
@@ -145,6 +201,11 @@ impl FrameConsumer {
 		let chunks = &state.chunks[self.index..];
 		self.index = state.chunks.len();
 
+		// Avoid a copy in the common case where the frame was written in one go.
+		if let [chunk] = chunks {
+			return Ok(chunk.clone());
+		}
+
 		// We know the final size so we can allocate the buffer upfront.
 		let size = chunks.iter().map(Bytes::len).sum();
 		let mut buf = BytesMut::with_capacity(size);
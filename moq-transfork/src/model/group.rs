@@ -8,12 +8,12 @@
 //!
 //! The stream is closed with [ServeError::MoqError] when all writers or readers are dropped.
 use bytes::Bytes;
-use std::ops;
+use std::{fmt, ops, sync::Arc};
 use tokio::sync::watch;
 
 use crate::Error;
 
-use super::{Frame, FrameConsumer, FrameProducer};
+use super::{Frame, FrameConsumer, FrameProducer, Memory};
 
 /// An independent group of frames.
 #[derive(Clone, PartialEq, Debug)]
@@ -29,31 +29,83 @@ impl Group {
 	}
 
 	pub fn produce(self) -> (GroupProducer, GroupConsumer) {
-		let (send, recv) = watch::channel(GroupState::default());
+		self.produce_limited(None, None, None)
+	}
 
-		let writer = GroupProducer::new(send, self.clone());
+	// Produce a group that can't exceed the given size or the memory limit.
+	// If the memory is exhausted, reclaim is called to free up memory before giving up.
+	pub(super) fn produce_limited(
+		self,
+		limit: Option<usize>,
+		memory: Option<Memory>,
+		reclaim: Option<Reclaim>,
+	) -> (GroupProducer, GroupConsumer) {
+		let (send, recv) = watch::channel(GroupState::new(limit, memory.clone()));
+
+		let writer = GroupProducer::new(send, memory, reclaim, self.clone());
 		let reader = GroupConsumer::new(recv, self);
 
 		(writer, reader)
 	}
 }
 
+// Frees up memory by evicting another group, returning false if there was nothing to evict.
+#[derive(Clone)]
+pub(super) struct Reclaim(Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl Reclaim {
+	pub fn new<F: Fn() -> bool + Send + Sync + 'static>(f: F) -> Self {
+		Self(Arc::new(f))
+	}
+}
+
+impl fmt::Debug for Reclaim {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Reclaim").finish()
+	}
+}
+
 #[derive(Debug)]
 struct GroupState {
 	// The frames that has been written thus far
 	frames: Vec<FrameConsumer>,
 
+	// The sum of each frame's size.
+	size: usize,
+
+	// The maximum size of the group, if any.
+	limit: Option<usize>,
+
+	// The size is counted against this until the group is dropped.
+	memory: Option<Memory>,
+
 	// Set when the writer or all readers are dropped.
 	closed: Result<(), Error>,
 }
 
-impl Default for GroupState {
-	fn default() -> Self {
+impl GroupState {
+	fn new(limit: Option<usize>, memory: Option<Memory>) -> Self {
 		Self {
 			frames: Vec::new(),
+			size: 0,
+			limit,
+			memory,
 			closed: Ok(()),
 		}
 	}
+
+	// Returns true if a new frame would exceed the maximum size.
+	fn exceeds(&self, size: usize) -> bool {
+		self.limit.is_some_and(|limit| self.size + size > limit)
+	}
+}
+
+impl Drop for GroupState {
+	fn drop(&mut self) {
+		if let Some(memory) = &self.memory {
+			memory.release(self.size);
+		}
+	}
 }
 
 /// Create a group, frame-by-frame.
@@ -64,11 +116,20 @@ pub struct GroupProducer {
 
 	// Immutable stream state.
 	pub info: Group,
+
+	// Each frame is counted against this memory, calling reclaim to make room if needed.
+	memory: Option<Memory>,
+	reclaim: Option<Reclaim>,
 }
 
 impl GroupProducer {
-	fn new(state: watch::Sender<GroupState>, info: Group) -> Self {
-		Self { state, info }
+	fn new(state: watch::Sender<GroupState>, memory: Option<Memory>, reclaim: Option<Reclaim>, info: Group) -> Self {
+		Self {
+			state,
+			info,
+			memory,
+			reclaim,
+		}
 	}
 
 	// Write a frame in one go
@@ -78,10 +139,81 @@ impl GroupProducer {
 	}
 
 	// Create a frame with an upfront size
+	//
+	// If the frame would exceed a limit, the group is reset with [Error::TooLarge] and anything written to the frame is discarded.
 	pub fn create_frame(&mut self, size: usize) -> FrameProducer {
+		match self.try_create_frame(size) {
+			Ok(writer) => writer,
+			Err(err) => {
+				let (writer, _) = Frame::new(size).produce();
+				writer.clone().close(err);
+				writer
+			}
+		}
+	}
+
+	/// Create a frame with an upfront size, or return an error if the group is closed.
+	///
+	/// The group is reset with [Error::TooLarge] if the frame would exceed a limit.
+	pub fn try_create_frame(&mut self, size: usize) -> Result<FrameProducer, Error> {
+		// Reserve the memory before locking the group, as making room locks the track.
+		let exceeds = self.state.borrow().exceeds(size);
+		let reserved = match exceeds {
+			true => Err(Error::TooLarge),
+			false => self.reserve(size),
+		};
+
 		let (writer, reader) = Frame::new(size).produce();
-		self.state.send_modify(|state| state.frames.push(reader));
-		writer
+		let mut res = Ok(());
+
+		self.state.send_if_modified(|state| {
+			if let Err(err) = state.closed.clone() {
+				res = Err(err);
+				return false;
+			}
+
+			// Check again, as another clone of the producer may have written a frame in the meantime.
+			let err = match &reserved {
+				Err(err) => Some(err.clone()),
+				Ok(()) if state.exceeds(size) => Some(Error::TooLarge),
+				Ok(()) => None,
+			};
+
+			if let Some(err) = err {
+				state.closed = Err(err.clone());
+				res = Err(err);
+				return true;
+			}
+
+			state.size += size;
+			state.frames.push(reader);
+			true
+		});
+
+		// Don't count the frame if it wasn't added.
+		if let (Err(_), Ok(()), Some(memory)) = (&res, reserved, &self.memory) {
+			memory.release(size);
+		}
+
+		res.map(|_| writer)
+	}
+
+	// Count a new frame against the memory, reclaiming older groups until it fits.
+	fn reserve(&self, size: usize) -> Result<(), Error> {
+		let memory = match &self.memory {
+			Some(memory) => memory,
+			None => return Ok(()),
+		};
+
+		loop {
+			match memory.reserve(size) {
+				Ok(()) => return Ok(()),
+				Err(err) => match &self.reclaim {
+					Some(reclaim) if (reclaim.0)() => continue,
+					_ => return Err(err),
+				},
+			}
+		}
 	}
 
 	pub fn frame_count(&self) -> usize {
@@ -179,6 +311,11 @@ impl GroupConsumer {
 		}
 	}
 
+	/// The total size of all frames written thus far, including any incomplete frames.
+	pub fn size(&self) -> usize {
+		self.state.borrow().size
+	}
+
 	pub async fn closed(&self) -> Result<(), Error> {
 		match self.state.clone().wait_for(|state| state.closed.is_err()).await {
 			Ok(state) => state.closed.clone(),
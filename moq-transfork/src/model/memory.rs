@@ -0,0 +1,147 @@
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	Arc,
+};
+
+use crate::Error;
+
+/// Counts the bytes buffered by groups, with an optional limit.
+///
+/// Bytes are counted when a frame is created and released when every handle to the group is dropped.
+/// A counter can be shared by many tracks via [super::TrackLimits::memory], for example to enforce a global budget.
+/// Each track counts its own bytes with a [Self::child], so bytes are counted against both the track and the shared budget.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+	state: Arc<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+	used: AtomicUsize,
+	limit: Option<usize>,
+
+	// Any bytes are also counted against the parent.
+	parent: Option<Memory>,
+}
+
+impl Memory {
+	/// A counter without a limit, used only for reporting.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// A counter that refuses to buffer more than the given number of bytes.
+	pub fn limit(limit: usize) -> Self {
+		Self {
+			state: Arc::new(MemoryState {
+				limit: Some(limit),
+				..Default::default()
+			}),
+		}
+	}
+
+	/// A counter with its own optional limit, whose bytes also count against this one.
+	pub fn child(&self, limit: Option<usize>) -> Self {
+		Self {
+			state: Arc::new(MemoryState {
+				limit,
+				parent: Some(self.clone()),
+				..Default::default()
+			}),
+		}
+	}
+
+	/// The number of bytes currently buffered.
+	pub fn used(&self) -> usize {
+		self.state.used.load(Ordering::Relaxed)
+	}
+
+	/// The maximum number of bytes, if any.
+	pub fn max(&self) -> Option<usize> {
+		self.state.limit
+	}
+
+	// Count the bytes, or return [Error::TooLarge] if it would exceed this limit or any parent's.
+	pub(super) fn reserve(&self, size: usize) -> Result<(), Error> {
+		let limit = self.state.limit.unwrap_or(usize::MAX);
+
+		self.state
+			.used
+			.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+				used.checked_add(size).filter(|total| *total <= limit)
+			})
+			.map_err(|_| Error::TooLarge)?;
+
+		if let Some(parent) = &self.state.parent {
+			if let Err(err) = parent.reserve(size) {
+				self.state.used.fetch_sub(size, Ordering::Relaxed);
+				return Err(err);
+			}
+		}
+
+		Ok(())
+	}
+
+	pub(super) fn release(&self, size: usize) {
+		self.state.used.fetch_sub(size, Ordering::Relaxed);
+
+		if let Some(parent) = &self.state.parent {
+			parent.release(size);
+		}
+	}
+}
+
+// Compare by identity, so tracks sharing a counter are equal.
+impl PartialEq for Memory {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.state, &other.state)
+	}
+}
+
+impl Eq for Memory {}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn reserve() {
+		let memory = Memory::limit(10);
+		assert!(memory.reserve(6).is_ok());
+		assert!(matches!(memory.reserve(6), Err(Error::TooLarge)));
+		assert!(memory.reserve(4).is_ok());
+		assert_eq!(memory.used(), 10);
+
+		memory.release(6);
+		assert_eq!(memory.used(), 4);
+
+		// Clones share the same counter.
+		let shared = memory.clone();
+		assert!(shared.reserve(6).is_ok());
+		assert_eq!(memory.used(), 10);
+		assert_eq!(memory, shared);
+		assert_ne!(memory, Memory::new());
+	}
+
+	#[test]
+	fn child() {
+		let parent = Memory::limit(10);
+		let first = parent.child(Some(6));
+		let second = parent.child(None);
+
+		// The child's limit applies first.
+		assert!(matches!(first.reserve(7), Err(Error::TooLarge)));
+		assert!(first.reserve(6).is_ok());
+		assert_eq!(parent.used(), 6);
+
+		// Then the parent's, leaving the child unchanged on error.
+		assert!(matches!(second.reserve(5), Err(Error::TooLarge)));
+		assert_eq!(second.used(), 0);
+		assert!(second.reserve(4).is_ok());
+		assert_eq!(parent.used(), 10);
+
+		first.release(6);
+		assert_eq!(parent.used(), 4);
+		assert_eq!(first.used(), 0);
+	}
+}
@@ -1,6 +1,7 @@
 mod announced;
 mod frame;
 mod group;
+mod memory;
 mod path;
 mod router;
 mod track;
@@ -8,6 +9,7 @@ mod track;
 pub use announced::*;
 pub use frame::*;
 pub use group::*;
+pub use memory::*;
 pub use path::*;
 pub use router::*;
 pub use track::*;
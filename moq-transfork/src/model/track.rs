@@ -7,18 +7,28 @@
 //!
 //! A [TrackConsumer] may not receive all streams in order or at all.
 //! These streams are meant to be transmitted over congested networks and the key to MoQ Tranport is to not block on them.
+//! By default only the latest group is returned, but [TrackConsumer::ascending] will reorder groups and wait (up to a timeout) for missing ones.
 //! streams will be cached for a potentially limited duration added to the unreliable nature.
+//! The [TrackCache] determines how many recent groups are kept around for [TrackConsumer::get_group] and late joiners.
 //! A cloned [Consumer] will receive a copy of all new stream going forward (fanout).
 //!
 //! The track is closed with [Error] when all writers or readers are dropped.
 
 use tokio::sync::watch;
+use web_time::{Duration, Instant};
 
-use super::{Group, GroupConsumer, GroupProducer, Path};
-pub use crate::message::GroupOrder;
+use super::{Group, GroupConsumer, GroupProducer, Memory, Path, Reclaim};
+pub use crate::message::{Drain, GroupOrder};
 use crate::Error;
 
-use std::{cmp::Ordering, ops, sync::Arc};
+use std::{
+	collections::{BTreeMap, VecDeque},
+	ops,
+	sync::{Arc, OnceLock},
+};
+
+// The number of drops and resets kept around for slow consumers.
+const MAX_NOTICES: usize = 64;
 
 /// A track, a collection of indepedent groups (streams) with a specified order/priority.
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -31,6 +41,12 @@ pub struct Track {
 
 	/// The preferred order to deliver groups in the track.
 	pub order: GroupOrder,
+
+	/// The number of recent groups to keep in memory.
+	pub cache: TrackCache,
+
+	/// The maximum number of bytes to buffer, regardless of the cache.
+	pub limits: TrackLimits,
 }
 
 impl Track {
@@ -46,11 +62,33 @@ impl Track {
 	}
 
 	pub fn produce(self) -> (TrackProducer, TrackConsumer) {
-		let (send, recv) = watch::channel(TrackState::default());
-		let info = Arc::new(self);
+		let state = TrackState {
+			info: Some(TrackInfo {
+				priority: self.priority,
+				order: self.order,
+				group_latest: 0,
+			}),
+			..Default::default()
+		};
+
+		let (send, recv) = watch::channel(state);
+		let (update, updates) = watch::channel(None);
+		let update = Arc::new(update);
+
+		// Each track gets its own counter, charged against the shared budget too.
+		let memory = match (&self.limits.memory, self.limits.track) {
+			(Some(shared), limit) => shared.child(limit),
+			(None, Some(limit)) => Memory::limit(limit),
+			(None, None) => Memory::new(),
+		};
+
+		let info = Arc::new(Resolved {
+			requested: self,
+			resolved: OnceLock::new(),
+		});
 
-		let writer = TrackProducer::new(send, info.clone());
-		let reader = TrackConsumer::new(recv, info);
+		let writer = TrackProducer::new(send, update.clone(), updates, memory.clone(), info.clone());
+		let reader = TrackConsumer::new(recv, update, memory, info);
 
 		(writer, reader)
 	}
@@ -62,10 +100,135 @@ impl Default for Track {
 			path: Default::default(),
 			priority: 0,
 			order: GroupOrder::Desc,
+			cache: Default::default(),
+			limits: Default::default(),
 		}
 	}
 }
 
+/// The groups requested by a subscriber, separate from the [Track] so they don't affect the cache or limits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TrackRequest {
+	/// The first group to deliver, or the latest group if not set.
+	pub group_min: Option<u64>,
+
+	/// The last group to deliver, or unbounded if not set.
+	pub group_max: Option<u64>,
+
+	/// What to do with any groups in flight when the subscription is cancelled.
+	pub drain: Drain,
+}
+
+impl TrackRequest {
+	/// Only deliver groups within the given range of sequence numbers, ex. `100..=200` or `50..`.
+	pub fn groups<R: ops::RangeBounds<u64>>(mut self, range: R) -> Self {
+		self.group_min = match range.start_bound() {
+			ops::Bound::Included(&min) => Some(min),
+			ops::Bound::Excluded(&min) => Some(min + 1),
+			ops::Bound::Unbounded => None,
+		};
+
+		self.group_max = match range.end_bound() {
+			ops::Bound::Included(&max) => Some(max),
+			ops::Bound::Excluded(&max) => Some(max.saturating_sub(1)),
+			ops::Bound::Unbounded => None,
+		};
+
+		self
+	}
+
+	pub fn drain(mut self, drain: Drain) -> Self {
+		self.drain = drain;
+		self
+	}
+
+	/// Returns true if the request is for a specific range rather than the live track.
+	pub fn is_ranged(&self) -> bool {
+		self.group_min.is_some() || self.group_max.is_some()
+	}
+}
+
+/// Limits on the history of groups kept by a track.
+///
+/// Older groups are evicted when a new group is created and any of the limits are exceeded.
+/// The latest group is always kept, regardless of the limits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TrackCache {
+	/// The maximum number of groups.
+	pub groups: usize,
+
+	/// The maximum number of bytes across all groups, if any.
+	pub bytes: Option<usize>,
+
+	/// The maximum age of a group, if any.
+	pub age: Option<Duration>,
+}
+
+impl TrackCache {
+	/// Only keep the latest group.
+	pub const LATEST: Self = Self {
+		groups: 1,
+		bytes: None,
+		age: None,
+	};
+}
+
+impl Default for TrackCache {
+	fn default() -> Self {
+		Self::LATEST
+	}
+}
+
+/// Hard limits on the memory used by a track, protecting against slow consumers and broken publishers.
+///
+/// Unlike the [TrackCache], these apply while groups are written, including the latest group.
+/// A frame that would exceed any limit resets its group with [Error::TooLarge] instead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackLimits {
+	/// The maximum number of bytes in a single group, if any.
+	pub group: Option<usize>,
+
+	/// The maximum number of bytes buffered by the track, if any.
+	///
+	/// The oldest groups are evicted to make room, but they stay buffered while a slow consumer holds them.
+	/// A frame that still doesn't fit resets its group, without affecting other tracks.
+	pub track: Option<usize>,
+
+	/// A counter shared with other tracks, for example to enforce a global budget.
+	///
+	/// Each track counts against it through its own child counter, see [Memory::child].
+	pub memory: Option<Memory>,
+}
+
+/// The properties of a track as decided by the publisher, which may differ from what was requested.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrackInfo {
+	/// The priority of the track.
+	pub priority: i8,
+
+	/// The order the publisher delivers groups in.
+	pub order: GroupOrder,
+
+	/// The latest group sequence number, or 0 if there are no groups.
+	pub group_latest: u64,
+}
+
+/// A request from a consumer to change how a track is delivered.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrackUpdate {
+	/// The new priority of the track.
+	pub priority: i8,
+
+	/// The new order to deliver groups in the track.
+	pub order: GroupOrder,
+
+	/// The first group to deliver, or unchanged if not set.
+	pub group_min: Option<u64>,
+
+	/// The last group to deliver, or unbounded if not set.
+	pub group_max: Option<u64>,
+}
+
 /// Build a track with optional parameters.
 pub struct TrackBuilder {
 	track: Track,
@@ -99,6 +262,16 @@ impl TrackBuilder {
 		self
 	}
 
+	pub fn cache(mut self, cache: TrackCache) -> Self {
+		self.track.cache = cache;
+		self
+	}
+
+	pub fn limits(mut self, limits: TrackLimits) -> Self {
+		self.track.limits = limits;
+		self
+	}
+
 	pub fn produce(self) -> (TrackProducer, TrackConsumer) {
 		self.track.produce()
 	}
@@ -115,48 +288,201 @@ impl From<TrackBuilder> for Track {
 	}
 }
 
+#[derive(Debug)]
+struct CachedGroup {
+	group: GroupConsumer,
+	created: Instant,
+}
+
 #[derive(Debug)]
 struct TrackState {
-	latest: Option<GroupConsumer>,
+	// Recent groups, indexed by sequence number.
+	groups: BTreeMap<u64, CachedGroup>,
 	closed: Result<(), Error>,
+
+	// Unknown until the publisher replies, for a remote track.
+	info: Option<TrackInfo>,
+
+	// Recent drops and resets, read by each consumer with a cursor.
+	notices: VecDeque<Notice>,
+
+	// The total number of notices ever recorded.
+	notice_count: u64,
+}
+
+#[derive(Clone, Debug)]
+enum Notice {
+	Dropped { from: u64, to: u64 },
+	Reset { sequence: u64, code: u32 },
+}
+
+impl TrackState {
+	fn notify(&mut self, notice: Notice) {
+		if self.notices.len() >= MAX_NOTICES {
+			self.notices.pop_front();
+		}
+
+		self.notices.push_back(notice);
+		self.notice_count += 1;
+	}
+
+	fn latest(&self) -> Option<&GroupConsumer> {
+		self.groups.last_key_value().map(|(_, cached)| &cached.group)
+	}
+
+	// Evict the oldest groups until we're within the cache limits.
+	fn evict(&mut self, track: &Track) {
+		let cache = &track.cache;
+
+		while self.groups.len() > cache.groups.max(1) {
+			self.groups.pop_first();
+		}
+
+		if let Some(max) = cache.bytes {
+			let mut size: usize = self.groups.values().map(|cached| cached.group.size()).sum();
+
+			while size > max && self.groups.len() > 1 {
+				let (_, cached) = self.groups.pop_first().unwrap();
+				size -= cached.group.size();
+			}
+		}
+
+		if let Some(max) = cache.age {
+			let now = moq_async::now();
+
+			while self.groups.len() > 1 {
+				let (_, oldest) = self.groups.first_key_value().unwrap();
+				if now.duration_since(oldest.created) <= max {
+					break;
+				}
+
+				self.groups.pop_first();
+			}
+		}
+	}
+
+	// Evict the oldest group to free up memory, except for the latest group and the one being written.
+	fn reclaim(&mut self, keep: u64) -> bool {
+		let latest = self.groups.last_key_value().map(|(&sequence, _)| sequence);
+		let oldest = self
+			.groups
+			.keys()
+			.copied()
+			.find(|&sequence| sequence != keep && Some(sequence) != latest);
+
+		match oldest {
+			Some(sequence) => self.groups.remove(&sequence).is_some(),
+			None => false,
+		}
+	}
 }
 
 impl Default for TrackState {
 	fn default() -> Self {
 		Self {
-			latest: None,
+			groups: BTreeMap::new(),
 			closed: Ok(()),
+			info: None,
+			notices: VecDeque::new(),
+			notice_count: 0,
 		}
 	}
 }
 
+// The track as requested, replaced by the publisher's priority and order once known.
+#[derive(Debug)]
+struct Resolved {
+	requested: Track,
+	resolved: OnceLock<Track>,
+}
+
+impl Resolved {
+	fn get(&self) -> &Track {
+		self.resolved.get().unwrap_or(&self.requested)
+	}
+}
+
 /// A producer for a track, used to create new groups.
 #[derive(Clone, Debug)]
 pub struct TrackProducer {
-	pub info: Arc<Track>,
-	state: watch::Sender<TrackState>,
+	info: Arc<Resolved>,
+
+	// Groups hold a weak reference, so they can reclaim memory without keeping the track open.
+	state: Arc<watch::Sender<TrackState>>,
+
+	// The bytes buffered by every group in the track.
+	memory: Memory,
+
+	// Used to create new consumers.
+	update: Arc<watch::Sender<Option<TrackUpdate>>>,
+
+	// Any updates requested by the consumers.
+	updates: watch::Receiver<Option<TrackUpdate>>,
 }
 
 impl TrackProducer {
-	fn new(state: watch::Sender<TrackState>, info: Arc<Track>) -> Self {
-		Self { info, state }
+	fn new(
+		state: watch::Sender<TrackState>,
+		update: Arc<watch::Sender<Option<TrackUpdate>>>,
+		updates: watch::Receiver<Option<TrackUpdate>>,
+		memory: Memory,
+		info: Arc<Resolved>,
+	) -> Self {
+		Self {
+			info,
+			state: Arc::new(state),
+			update,
+			updates,
+			memory,
+		}
 	}
 
 	/// Build a new group with the given sequence number.
+	///
+	/// The group is cached unless the sequence number is a duplicate or older than everything in the cache.
 	pub fn create_group(&mut self, sequence: u64) -> GroupProducer {
+		let limits = &self.info.get().limits;
+		let limit = limits.group.into_iter().chain(limits.track).min();
+
+		// Make room by evicting older groups when the track is out of memory.
+		let state = Arc::downgrade(&self.state);
+		let reclaim = Reclaim::new(move || {
+			let Some(state) = state.upgrade() else { return false };
+
+			let mut evicted = false;
+			state.send_if_modified(|state| {
+				evicted = state.reclaim(sequence);
+				evicted
+			});
+
+			evicted
+		});
+
 		let group = Group::new(sequence);
-		let (writer, reader) = group.produce();
+		let (writer, reader) = group.produce_limited(limit, Some(self.memory.clone()), Some(reclaim));
 
 		self.state.send_if_modified(|state| {
-			if let Some(latest) = &state.latest {
-				match reader.sequence.cmp(&latest.sequence) {
-					Ordering::Less => return false,  // Not modified,
-					Ordering::Equal => return false, // TODO error?
-					Ordering::Greater => (),
+			if state.groups.contains_key(&sequence) {
+				// TODO error?
+				return false;
+			}
+
+			if state.groups.len() >= self.info.get().cache.groups.max(1) {
+				match state.groups.first_key_value() {
+					// Not modified, as it would be immediately evicted.
+					Some((&oldest, _)) if sequence < oldest => return false,
+					_ => (),
 				}
 			}
 
-			state.latest = Some(reader);
+			let cached = CachedGroup {
+				group: reader,
+				created: moq_async::now(),
+			};
+
+			state.groups.insert(sequence, cached);
+			state.evict(self.info.get());
+
 			true
 		});
 
@@ -166,16 +492,43 @@ impl TrackProducer {
 	/// Build a new group with the next sequence number.
 	pub fn append_group(&mut self) -> GroupProducer {
 		// TODO remove this extra lock
-		let sequence = self
-			.state
-			.borrow()
-			.latest
-			.as_ref()
-			.map_or(0, |group| group.sequence + 1);
+		let sequence = self.state.borrow().latest().map_or(0, |group| group.sequence + 1);
 
 		self.create_group(sequence)
 	}
 
+	/// Set the track info, replacing the priority and order from the [Track].
+	///
+	/// This is used when the info is determined by a remote publisher.
+	/// NOTE: Only the first info is applied to the [Track] returned via Deref, while [TrackConsumer::info] returns the latest.
+	pub fn set_info(&mut self, info: TrackInfo) {
+		let track = Track {
+			priority: info.priority,
+			order: info.order,
+			..self.info.requested.clone()
+		};
+		self.info.resolved.set(track).ok();
+
+		self.state.send_modify(|state| state.info = Some(info));
+	}
+
+	/// Clear the info until [Self::set_info] is called, causing [TrackConsumer::info] to block.
+	pub fn clear_info(&mut self) {
+		self.state.send_modify(|state| state.info = None);
+	}
+
+	/// Report that the groups `from..=to` will never be created, notifying [TrackConsumer::next_event].
+	pub fn drop_groups(&mut self, from: u64, to: u64) {
+		self.state
+			.send_modify(|state| state.notify(Notice::Dropped { from, to }));
+	}
+
+	/// Report that a group was reset with an error code, notifying [TrackConsumer::next_event].
+	pub fn reset_group(&mut self, sequence: u64, code: u32) {
+		self.state
+			.send_modify(|state| state.notify(Notice::Reset { sequence, code }));
+	}
+
 	/// Close the track with an error.
 	pub fn close(self, err: Error) {
 		self.state.send_modify(|state| {
@@ -185,7 +538,31 @@ impl TrackProducer {
 
 	/// Create a new consumer for the track.
 	pub fn subscribe(&self) -> TrackConsumer {
-		TrackConsumer::new(self.state.subscribe(), self.info.clone())
+		TrackConsumer::new(
+			self.state.subscribe(),
+			self.update.clone(),
+			self.memory.clone(),
+			self.info.clone(),
+		)
+	}
+
+	/// The number of bytes buffered by the track, including groups that are no longer cached but still being read.
+	pub fn buffered(&self) -> usize {
+		self.memory.used()
+	}
+
+	/// Block until a consumer requests an update via [TrackConsumer::update], returning the most recent request.
+	pub async fn updated(&mut self) -> TrackUpdate {
+		loop {
+			if self.updates.changed().await.is_err() {
+				// We hold a reference to the sender, so this will never happen.
+				std::future::pending::<()>().await;
+			}
+
+			if let Some(update) = self.updates.borrow_and_update().clone() {
+				return update;
+			}
+		}
 	}
 
 	/// Block until there are no active consumers.
@@ -198,48 +575,228 @@ impl ops::Deref for TrackProducer {
 	type Target = Track;
 
 	fn deref(&self) -> &Self::Target {
-		&self.info
+		self.info.get()
 	}
 }
 
+#[derive(Clone, Copy, Debug)]
+enum Delivery {
+	// Skip to the latest group.
+	Latest,
+
+	// Return groups in ascending order, waiting up to the timeout for missing groups.
+	Ascending(Duration),
+}
+
+/// An event returned by [TrackConsumer::next_event].
+#[derive(Clone, Debug)]
+pub enum TrackEvent {
+	/// A new group is available.
+	Group(GroupConsumer),
+
+	/// The groups `from..=to` were skipped or dropped and will not be returned.
+	Gap { from: u64, to: u64 },
+
+	/// A group was reset by the publisher or network, possibly after it was returned.
+	Reset { sequence: u64, code: u32 },
+}
+
 /// A consumer for a track, used to read groups.
 #[derive(Clone, Debug)]
 pub struct TrackConsumer {
-	pub info: Arc<Track>,
+	info: Arc<Resolved>,
 	state: watch::Receiver<TrackState>,
+	update: Arc<watch::Sender<Option<TrackUpdate>>>,
+	memory: Memory,
 	prev: Option<u64>, // The previous sequence number
+	delivery: Delivery,
+	gap: Option<Instant>, // When to give up on a missing group
+
+	notice: u64,                  // The next notice to read
+	reported: Option<u64>,        // The largest sequence number returned as a group or gap
+	events: VecDeque<TrackEvent>, // Events waiting to be returned
 }
 
 impl TrackConsumer {
-	fn new(state: watch::Receiver<TrackState>, info: Arc<Track>) -> Self {
+	fn new(
+		state: watch::Receiver<TrackState>,
+		update: Arc<watch::Sender<Option<TrackUpdate>>>,
+		memory: Memory,
+		info: Arc<Resolved>,
+	) -> Self {
+		// Only notify about drops and resets that occur after we subscribed.
+		let notice = state.borrow().notice_count;
+
 		Self {
 			state,
+			update,
+			memory,
 			info,
 			prev: None,
+			delivery: Delivery::Latest,
+			gap: None,
+			notice,
+			reported: None,
+			events: VecDeque::new(),
 		}
 	}
 
+	/// Return groups in ascending order from [Self::next_group], instead of skipping to the latest group.
+	///
+	/// A missing group is skipped once a newer group has been waiting for `timeout`.
+	/// Groups evicted from the [TrackCache] before they are read are also skipped, so size the cache accordingly.
+	pub fn ascending(mut self, timeout: Duration) -> Self {
+		self.delivery = Delivery::Ascending(timeout);
+		self
+	}
+
+	/// Return only the latest group from [Self::next_group], skipping any older groups.
+	///
+	/// This is the default.
+	pub fn latest(mut self) -> Self {
+		self.delivery = Delivery::Latest;
+		self.gap = None;
+		self
+	}
+
+	/// Ask the producer to change the priority, order, or range of groups.
+	///
+	/// For a network subscription, this is sent to the publisher, replacing any previous update.
+	/// NOTE: This applies to all consumers of the track, so the most recent update wins.
+	/// This includes consumers returned by [crate::Session::subscribe] for the same live track, which share a subscription.
+	/// Subscribe with a group range instead for a separate subscription that can be updated independently.
+	pub fn update(&self, update: TrackUpdate) {
+		self.update.send_replace(Some(update));
+	}
+
+	/// Return the group with the given sequence number, if it's still cached.
 	pub fn get_group(&self, sequence: u64) -> Result<GroupConsumer, Error> {
 		let state = self.state.borrow();
 
-		// TODO support more than just the latest group
-		if let Some(latest) = &state.latest {
-			if latest.sequence == sequence {
-				return Ok(latest.clone());
-			}
+		if let Some(cached) = state.groups.get(&sequence) {
+			return Ok(cached.group.clone());
 		}
 
 		state.closed.clone()?;
 		Err(Error::NotFound)
 	}
 
-	// NOTE: This can return groups out of order.
-	// TODO obey order
+	/// The number of bytes buffered by the track, including groups that are no longer cached but still being read.
+	pub fn buffered(&self) -> usize {
+		self.memory.used()
+	}
+
+	/// Return the sequence numbers of all cached groups, in ascending order.
+	pub fn cached_groups(&self) -> Vec<u64> {
+		self.state.borrow().groups.keys().copied().collect()
+	}
+
+	/// Return the next group, based on the delivery mode.
+	///
+	/// By default, this returns the latest group and may skip or reorder groups.
+	/// See [Self::ascending] to return groups in order.
 	pub async fn next_group(&mut self) -> Result<Option<GroupConsumer>, Error> {
+		match self.delivery {
+			Delivery::Latest => self.next_latest().await,
+			Delivery::Ascending(timeout) => self.next_ascending(timeout).await,
+		}
+	}
+
+	/// Return the next group, or an event when groups are skipped, dropped, or reset.
+	///
+	/// Groups are returned based on the delivery mode, just like [Self::next_group].
+	/// NOTE: Use either this or [Self::next_group], as groups returned by one will not be returned by the other.
+	pub async fn next_event(&mut self) -> Result<Option<TrackEvent>, Error> {
+		loop {
+			if let Some(event) = self.events.pop_front() {
+				return Ok(Some(event));
+			}
+
+			if self.read_notices() {
+				continue;
+			}
+
+			let mut state = self.state.clone();
+			let notice = self.notice;
+
+			tokio::select! {
+				biased;
+				// Wait for a new notice, which can't happen once the producer is dropped.
+				_ = async {
+					if state.wait_for(|state| state.notice_count > notice).await.is_err() {
+						std::future::pending::<()>().await;
+					}
+				} => {},
+				res = self.next_group() => match res? {
+					Some(group) => self.recv_group(group),
+					// Return any final notices before we're done.
+					None if !self.read_notices() => return Ok(None),
+					None => {},
+				}
+			}
+		}
+	}
+
+	// Queue an event for any new notices, returning true if there were any.
+	fn read_notices(&mut self) -> bool {
+		let notices: Vec<Notice> = {
+			let state = self.state.borrow();
+
+			// Skip any notices we missed because we were too slow.
+			let first = state.notice_count - state.notices.len() as u64;
+			let skip = self.notice.saturating_sub(first) as usize;
+			self.notice = state.notice_count;
+
+			state.notices.iter().skip(skip).cloned().collect()
+		};
+
+		for notice in notices {
+			match notice {
+				Notice::Dropped { from, to } => self.recv_gap(from, to),
+				Notice::Reset { sequence, code } => self.events.push_back(TrackEvent::Reset { sequence, code }),
+			}
+		}
+
+		!self.events.is_empty()
+	}
+
+	fn recv_group(&mut self, group: GroupConsumer) {
+		if let Some(reported) = self.reported {
+			if group.sequence > reported + 1 {
+				self.events.push_back(TrackEvent::Gap {
+					from: reported + 1,
+					to: group.sequence - 1,
+				});
+			}
+		}
+
+		self.reported = Some(
+			self.reported
+				.map_or(group.sequence, |reported| reported.max(group.sequence)),
+		);
+		self.events.push_back(TrackEvent::Group(group));
+	}
+
+	fn recv_gap(&mut self, from: u64, to: u64) {
+		// Ignore any groups we've already reported.
+		let from = match self.reported {
+			Some(reported) => from.max(reported + 1),
+			None => from,
+		};
+
+		if from > to {
+			return;
+		}
+
+		self.reported = Some(to);
+		self.events.push_back(TrackEvent::Gap { from, to });
+	}
+
+	async fn next_latest(&mut self) -> Result<Option<GroupConsumer>, Error> {
 		// Wait until there's a new latest group or the track is closed.
 		let state = match self
 			.state
-			.wait_for(|state| state.latest.as_ref().map(|group| group.sequence) != self.prev || state.closed.is_err())
+			.wait_for(|state| state.latest().map(|group| group.sequence) != self.prev || state.closed.is_err())
 			.await
 		{
 			Ok(state) => state,
@@ -247,7 +804,7 @@ impl TrackConsumer {
 		};
 
 		// If there's a new latest group, return it.
-		if let Some(group) = state.latest.as_ref() {
+		if let Some(group) = state.latest() {
 			if Some(group.sequence) != self.prev {
 				self.prev = Some(group.sequence);
 				return Ok(Some(group.clone()));
@@ -258,10 +815,77 @@ impl TrackConsumer {
 		Err(state.closed.clone().unwrap_err())
 	}
 
+	async fn next_ascending(&mut self, timeout: Duration) -> Result<Option<GroupConsumer>, Error> {
+		loop {
+			// The producer was dropped, so no more groups will be created.
+			let dropped = self.state.has_changed().is_err();
+
+			{
+				let state = self.state.borrow_and_update();
+				let closed = dropped || state.closed.is_err();
+
+				let next = match self.prev {
+					Some(prev) => state.groups.range(prev + 1..).next(),
+					None => state.groups.first_key_value(),
+				};
+
+				match next {
+					Some((&sequence, cached)) => {
+						let expected = self.prev.map(|prev| prev + 1).unwrap_or(sequence);
+						let expired = self.gap.is_some_and(|gap| moq_async::now() >= gap);
+
+						// Skip the missing groups if they'll never arrive or we've waited long enough.
+						if sequence == expected || closed || expired {
+							self.prev = Some(sequence);
+							self.gap = None;
+							return Ok(Some(cached.group.clone()));
+						}
+
+						self.gap.get_or_insert_with(|| moq_async::now() + timeout);
+					}
+					None if dropped => return Ok(None),
+					None => state.closed.clone()?,
+				}
+			}
+
+			let remaining = self.gap.map(|gap| gap.saturating_duration_since(moq_async::now()));
+
+			tokio::select! {
+				_ = self.state.changed() => {},
+				_ = async { moq_async::sleep(remaining.unwrap()).await }, if remaining.is_some() => {},
+			}
+		}
+	}
+
+	/// Block until the track info is known, which requires a round trip for a remote track.
+	///
+	/// The latest group is updated with any groups received since.
+	pub async fn info(&self) -> Result<TrackInfo, Error> {
+		let mut state = self.state.clone();
+		let state = match state
+			.wait_for(|state| state.info.is_some() || state.closed.is_err())
+			.await
+		{
+			Ok(state) => state,
+			Err(_) => return Err(Error::Cancel),
+		};
+
+		let mut info = match &state.info {
+			Some(info) => info.clone(),
+			None => return Err(state.closed.clone().unwrap_err()),
+		};
+
+		if let Some(latest) = state.latest() {
+			info.group_latest = info.group_latest.max(latest.sequence);
+		}
+
+		Ok(info)
+	}
+
 	// Returns the largest group
 	pub fn latest_group(&self) -> u64 {
 		let state = self.state.borrow();
-		state.latest.as_ref().map(|group| group.sequence).unwrap_or_default()
+		state.latest().map(|group| group.sequence).unwrap_or_default()
 	}
 
 	pub async fn closed(&self) -> Result<(), Error> {
@@ -276,6 +900,313 @@ impl ops::Deref for TrackConsumer {
 	type Target = Track;
 
 	fn deref(&self) -> &Self::Target {
-		&self.info
+		self.info.get()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use bytes::Bytes;
+	use futures::FutureExt;
+
+	#[test]
+	fn latest_only() {
+		let (mut producer, consumer) = Track::new(Path::default().push("test")).produce();
+
+		producer.append_group();
+		producer.append_group();
+
+		assert!(matches!(consumer.get_group(0), Err(Error::NotFound)));
+		assert_eq!(consumer.get_group(1).unwrap().sequence, 1);
+		assert_eq!(consumer.cached_groups(), vec![1]);
+
+		// Older groups are ignored.
+		producer.create_group(0);
+		assert_eq!(consumer.cached_groups(), vec![1]);
+	}
+
+	#[test]
+	fn cache_groups() {
+		let cache = TrackCache {
+			groups: 3,
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).produce();
+
+		for _ in 0..5 {
+			producer.append_group();
+		}
+
+		assert_eq!(consumer.cached_groups(), vec![2, 3, 4]);
+		assert!(matches!(consumer.get_group(1), Err(Error::NotFound)));
+		assert_eq!(consumer.get_group(2).unwrap().sequence, 2);
+		assert_eq!(consumer.latest_group(), 4);
+
+		// Out of order groups are cached if they're newer than the oldest.
+		producer.create_group(7);
+		producer.create_group(5);
+		producer.create_group(1);
+		assert_eq!(consumer.cached_groups(), vec![4, 5, 7]);
+	}
+
+	#[test]
+	fn cache_bytes() {
+		let cache = TrackCache {
+			groups: 10,
+			bytes: Some(10),
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).produce();
+
+		producer.append_group().write_frame("hello");
+		producer.append_group().write_frame("world");
+		producer.append_group().write_frame("!");
+		assert_eq!(consumer.cached_groups(), vec![0, 1, 2]);
+
+		// Groups are only evicted when a new group is created.
+		producer.append_group();
+		assert_eq!(consumer.cached_groups(), vec![1, 2, 3]);
+
+		producer.append_group().write_frame("this is too large");
+		producer.append_group();
+		assert_eq!(consumer.cached_groups(), vec![5]);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn cache_age() {
+		let cache = TrackCache {
+			groups: 10,
+			age: Some(Duration::from_millis(50)),
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).produce();
+
+		producer.append_group();
+		producer.append_group();
+		tokio::time::advance(Duration::from_millis(100)).await;
+
+		producer.append_group();
+		assert_eq!(consumer.cached_groups(), vec![2]);
+	}
+
+	#[tokio::test]
+	async fn limits_group() {
+		let limits = TrackLimits {
+			group: Some(10),
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").limits(limits).produce();
+
+		let mut group = producer.append_group();
+		group.write_frame("hello");
+		group.write_frame("world");
+		assert_eq!(consumer.buffered(), 10);
+
+		// The group is reset instead of growing without bound.
+		assert!(matches!(group.try_create_frame(1), Err(Error::TooLarge)));
+		group.write_frame("!");
+
+		let mut reader = consumer.get_group(0).unwrap();
+		assert_eq!(reader.read_frame().await.unwrap().unwrap(), "hello");
+		assert_eq!(reader.read_frame().await.unwrap().unwrap(), "world");
+		assert!(matches!(reader.read_frame().await, Err(Error::TooLarge)));
+
+		// The memory is released once every handle to the group is dropped.
+		drop(group);
+		drop(reader);
+		producer.append_group();
+		assert_eq!(consumer.buffered(), 0);
+	}
+
+	#[test]
+	fn limits_track() {
+		let memory = Memory::new();
+		let limits = TrackLimits {
+			track: Some(10),
+			memory: Some(memory.clone()),
+			..Default::default()
+		};
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).limits(limits).produce();
+
+		// The oldest group is evicted to make room.
+		producer.append_group().write_frame("hello");
+		producer.append_group().write_frame("world!");
+		assert_eq!(consumer.cached_groups(), vec![1]);
+		assert_eq!(consumer.buffered(), 6);
+		assert_eq!(memory.used(), 6);
+
+		// A slow consumer holds onto the group, so evicting it doesn't free anything.
+		let reader = consumer.get_group(1).unwrap();
+		let mut group = producer.append_group();
+		assert!(matches!(group.try_create_frame(10), Err(Error::TooLarge)));
+		assert!(memory.used() <= 10);
+
+		// The memory is released once the consumer catches up.
+		drop(reader);
+		let mut group = producer.append_group();
+		group.write_frame("0123456789");
+		assert_eq!(memory.used(), 10);
+	}
+
+	#[test]
+	fn limits_slow_consumer() {
+		let memory = Memory::limit(20);
+		let limits = TrackLimits {
+			track: Some(10),
+			memory: Some(memory.clone()),
+			..Default::default()
+		};
+		let (mut slow, slow_consumer) = Track::build().path("slow").limits(limits.clone()).produce();
+		let (mut fast, fast_consumer) = Track::build().path("fast").limits(limits).produce();
+
+		// The slow consumer never reads, holding onto every group.
+		let mut held = Vec::new();
+		let mut reset = false;
+		for _ in 0..10 {
+			let mut group = slow.append_group();
+			held.push(slow_consumer.get_group(group.info.sequence).unwrap());
+
+			if group.try_create_frame(4).is_err() {
+				reset = true;
+				break;
+			}
+		}
+
+		// Only the slow track is reset, as it can't use more than its share of the budget.
+		assert!(reset);
+		assert!(slow_consumer.buffered() <= 10);
+
+		let mut group = fast.append_group();
+		group.write_frame("0123456789");
+		assert_eq!(fast_consumer.buffered(), 10);
+		assert!(memory.used() <= 20);
+	}
+
+	#[test]
+	fn frame_size() {
+		let (mut producer, _consumer) = Track::new(Path::default().push("test")).produce();
+		let mut group = producer.append_group();
+
+		// Writing more than the upfront size closes the frame.
+		let mut frame = group.create_frame(5);
+		frame.write("hello");
+		frame.write("!");
+
+		let mut reader = frame.subscribe();
+		assert_eq!(reader.read().now_or_never().unwrap().unwrap().unwrap(), "hello");
+		assert!(matches!(reader.read().now_or_never().unwrap(), Err(Error::WrongSize)));
+	}
+
+	#[test]
+	fn frame_chunks() {
+		let (mut producer, _consumer) = Track::new(Path::default().push("test")).produce();
+		let mut group = producer.append_group();
+
+		let mut frame = group.create_frame(11);
+		let mut reader = frame.subscribe();
+
+		frame.write("hello");
+		frame.write_chunks([Bytes::from_static(b" "), Bytes::from_static(b"world")]);
+
+		// Every available chunk is returned at once.
+		let chunks = reader.read_chunks().now_or_never().unwrap().unwrap().unwrap();
+		assert_eq!(chunks, vec!["hello", " ", "world"]);
+		assert!(reader.read_chunks().now_or_never().is_none());
+
+		drop(frame);
+		assert_eq!(reader.read_chunks().now_or_never().unwrap().unwrap(), None);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn ascending() {
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).produce();
+		let mut consumer = consumer.ascending(Duration::from_secs(10));
+
+		producer.create_group(0);
+		producer.create_group(2);
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+
+		// Wait for the missing group instead of skipping it.
+		let next = tokio::spawn(async move {
+			let a = consumer.next_group().await.unwrap().unwrap().sequence;
+			let b = consumer.next_group().await.unwrap().unwrap().sequence;
+			(a, b)
+		});
+
+		// Time is paused, so this only returns once the consumer is waiting.
+		tokio::time::sleep(Duration::from_millis(10)).await;
+		producer.create_group(1);
+
+		assert_eq!(next.await.unwrap(), (1, 2));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn ascending_gap() {
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+		let (mut producer, consumer) = Track::build().path("test").cache(cache).produce();
+		let mut consumer = consumer.ascending(Duration::from_millis(50));
+
+		producer.create_group(0);
+		producer.create_group(2);
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+
+		// The missing group is skipped after the timeout.
+		let start = tokio::time::Instant::now();
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 2);
+		assert_eq!(start.elapsed(), Duration::from_millis(50));
+
+		// Don't wait for missing groups once the producer is gone.
+		producer.create_group(5);
+		drop(producer);
+
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 5);
+		assert!(consumer.next_group().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn events() {
+		let (mut producer, mut consumer) = Track::new(Path::default().push("test")).produce();
+
+		producer.create_group(0);
+		assert!(matches!(consumer.next_event().await, Ok(Some(TrackEvent::Group(group))) if group.sequence == 0));
+
+		// Skipped sequence numbers are reported as a gap.
+		producer.create_group(2);
+		assert!(matches!(
+			consumer.next_event().await,
+			Ok(Some(TrackEvent::Gap { from: 1, to: 1 }))
+		));
+		assert!(matches!(consumer.next_event().await, Ok(Some(TrackEvent::Group(group))) if group.sequence == 2));
+
+		producer.drop_groups(3, 4);
+		producer.reset_group(2, 7);
+		assert!(matches!(
+			consumer.next_event().await,
+			Ok(Some(TrackEvent::Gap { from: 3, to: 4 }))
+		));
+		assert!(matches!(
+			consumer.next_event().await,
+			Ok(Some(TrackEvent::Reset { sequence: 2, code: 7 }))
+		));
+
+		// Dropped groups are not reported again.
+		producer.create_group(5);
+		assert!(matches!(consumer.next_event().await, Ok(Some(TrackEvent::Group(group))) if group.sequence == 5));
+
+		drop(producer);
+		assert!(matches!(consumer.next_event().await, Ok(None)));
 	}
 }
@@ -0,0 +1,161 @@
+use crate::{message, Error};
+
+use moq_async::OrClose;
+
+use super::{Fairness, Session, Stats, Stream, Transport, TransportStats};
+
+/// Configure the MoQ handshake, then [Self::connect] or [Self::accept] a session.
+///
+/// Any extensions sent by the peer are available via [Session::extensions] after the handshake.
+#[derive(Clone, Debug)]
+pub struct SessionBuilder {
+	versions: message::Versions,
+	extensions: message::Extensions,
+	fairness: Fairness,
+	transport: Option<Transport>,
+}
+
+impl Default for SessionBuilder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SessionBuilder {
+	pub fn new() -> Self {
+		Self {
+			versions: message::Version::SUPPORTED.into(),
+			extensions: Default::default(),
+			fairness: Default::default(),
+			transport: None,
+		}
+	}
+
+	/// The versions to support, in preferred order.
+	pub fn versions<V: Into<message::Versions>>(mut self, versions: V) -> Self {
+		self.versions = versions.into();
+		self
+	}
+
+	/// Only publish or subscribe, refusing any streams from the peer that don't fit the role.
+	///
+	/// This is sent as an extension and defaults to [message::Role::Both].
+	pub fn role(self, role: message::Role) -> Self {
+		self.extension(role)
+	}
+
+	/// Send an extension to the peer, replacing any previous extension with the same ID.
+	pub fn extension<E: message::Extension>(mut self, extension: E) -> Self {
+		self.extensions.set(extension);
+		self
+	}
+
+	/// How to share bandwidth between published tracks with the same priority, defaulting to [Fairness::Sequence].
+	pub fn fairness(mut self, fairness: Fairness) -> Self {
+		self.fairness = fairness;
+		self
+	}
+
+	/// Include statistics from the underlying transport in [Session::stats], ex. the RTT and congestion window.
+	///
+	/// For quinn, keep a clone of the session and use `move || TransportStats::from(&session)`.
+	pub fn transport<F: Fn() -> TransportStats + Send + Sync + 'static>(mut self, stats: F) -> Self {
+		self.transport = Some(Transport::new(stats));
+		self
+	}
+
+	/// Perform the MoQ handshake as a client.
+	pub async fn connect<T: Into<web_transport::Session>>(self, session: T) -> Result<Session, Error> {
+		let mut session = session.into();
+		let fairness = self.fairness;
+		let stats = Stats::new(self.transport.clone());
+		let mut stream = Stream::open(&mut session, message::ControlType::Session).await?;
+		let (server, role) = self.connect_setup(&mut stream).await.or_close(&mut stream)?;
+		Ok(Session::new(
+			session,
+			stream,
+			server.version,
+			role,
+			server.extensions,
+			fairness,
+			stats,
+		))
+	}
+
+	async fn connect_setup(self, setup: &mut Stream) -> Result<(message::ServerSetup, message::Role), Error> {
+		let role = self.role_local()?;
+
+		let client = message::ClientSetup {
+			versions: self.versions.clone(),
+			extensions: self.extensions,
+		};
+
+		setup.writer.encode(&client).await?;
+		let server: message::ServerSetup = setup.reader.decode().await?;
+
+		// The server must pick one of our versions.
+		if !self.versions.contains(&server.version) {
+			return Err(Error::Version(self.versions, [server.version].into()));
+		}
+
+		let role = Self::role_negotiate(role, &server.extensions)?;
+
+		tracing::info!(version = ?server.version, ?role, "connected");
+
+		Ok((server, role))
+	}
+
+	/// Perform the MoQ handshake as a server.
+	///
+	/// The client's preferred order is used to pick a version supported by both sides.
+	pub async fn accept<T: Into<web_transport::Session>>(self, session: T) -> Result<Session, Error> {
+		let mut session = session.into();
+		let mut stream = Stream::accept(&mut session).await?;
+		let kind = stream.reader.decode().await?;
+
+		if kind != message::ControlType::Session {
+			return Err(Error::UnexpectedStream(kind));
+		}
+
+		let fairness = self.fairness;
+		let stats = Stats::new(self.transport.clone());
+		let (version, role, extensions) = self.accept_setup(&mut stream).await.or_close(&mut stream)?;
+		Ok(Session::new(
+			session, stream, version, role, extensions, fairness, stats,
+		))
+	}
+
+	async fn accept_setup(
+		self,
+		control: &mut Stream,
+	) -> Result<(message::Version, message::Role, message::Extensions), Error> {
+		let client: message::ClientSetup = control.reader.decode().await?;
+		let role = Self::role_negotiate(self.role_local()?, &client.extensions)?;
+
+		let version = client
+			.versions
+			.select(&self.versions)
+			.ok_or_else(|| Error::Version(client.versions.clone(), self.versions))?;
+
+		let server = message::ServerSetup {
+			version,
+			extensions: self.extensions,
+		};
+
+		control.writer.encode(&server).await?;
+
+		tracing::info!(?version, ?role, "connected");
+
+		Ok((version, role, client.extensions))
+	}
+
+	fn role_local(&self) -> Result<message::Role, Error> {
+		Ok(self.extensions.get::<message::Role>()?.unwrap_or_default())
+	}
+
+	// Returns what we're allowed to do based on both roles.
+	fn role_negotiate(ours: message::Role, extensions: &message::Extensions) -> Result<message::Role, Error> {
+		let theirs = extensions.get::<message::Role>()?.unwrap_or_default();
+		ours.downgrade(theirs).ok_or(Error::RoleIncompatible(ours, theirs))
+	}
+}
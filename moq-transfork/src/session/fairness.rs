@@ -0,0 +1,20 @@
+/// How the publisher shares bandwidth between tracks with the same priority.
+///
+/// Groups from tracks with a higher priority are always sent first; this only breaks ties.
+/// Configured per session via [super::SessionBuilder::fairness].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fairness {
+	/// Compare group sequence numbers across tracks.
+	///
+	/// This is cheap, but a track with larger (or smaller) sequence numbers will starve the others.
+	#[default]
+	Sequence,
+
+	/// Take turns between tracks, so each gets an equal share.
+	///
+	/// Each group is ranked relative to the other groups being served for the same subscription,
+	/// so the newest (or oldest) group of every track ties and the transport round-robins between them.
+	/// When MAX_STREAMS is exhausted, tied subscriptions take turns opening the next stream.
+	/// NOTE: Once open, this relies on the QUIC implementation being fair to streams of equal priority, which is the default for quinn.
+	RoundRobin,
+}
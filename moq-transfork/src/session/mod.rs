@@ -1,15 +1,28 @@
-use crate::{message, AnnouncedConsumer, Error, Path, RouterConsumer, Track, TrackConsumer};
+use crate::{
+	message, AnnouncedConsumer, AnnouncedFilter, AnnouncedProducer, Error, Group, GroupConsumer, Path, RouterConsumer,
+	Track, TrackConsumer, TrackInfo, TrackProducer, TrackRequest,
+};
 
 use moq_async::{spawn, Close, OrClose};
 
+use std::sync::Arc;
+
+mod builder;
+mod fairness;
 mod publisher;
 mod reader;
+mod scheduler;
+mod stats;
 mod stream;
 mod subscriber;
 mod writer;
 
+pub use builder::*;
+pub use fairness::*;
 use publisher::*;
 use reader::*;
+use scheduler::*;
+pub use stats::*;
 use stream::*;
 use subscriber::*;
 use writer::*;
@@ -21,26 +34,42 @@ use writer::*;
 #[derive(Clone)]
 pub struct Session {
 	webtransport: web_transport::Session,
+	version: message::Version,
+	role: message::Role,
+	extensions: Arc<message::Extensions>,
 	publisher: Publisher,
 	subscriber: Subscriber,
+	stats: Stats,
 }
 
 impl Session {
-	fn new(mut session: web_transport::Session, stream: Stream) -> Self {
-		let publisher = Publisher::new(session.clone());
-		let subscriber = Subscriber::new(session.clone());
+	fn new(
+		mut session: web_transport::Session,
+		stream: Stream,
+		version: message::Version,
+		role: message::Role,
+		extensions: message::Extensions,
+		fairness: Fairness,
+		stats: Stats,
+	) -> Self {
+		let publisher = Publisher::new(session.clone(), version, fairness, stats.clone());
+		let subscriber = Subscriber::new(session.clone(), version, stats.clone());
 
 		let this = Self {
 			webtransport: session.clone(),
+			version,
+			role,
+			extensions: Arc::new(extensions),
 			publisher: publisher.clone(),
 			subscriber: subscriber.clone(),
+			stats,
 		};
 
 		spawn(async move {
 			let res = tokio::select! {
 				res = Self::run_session(stream) => res,
-				res = Self::run_bi(session.clone(), publisher) => res,
-				res = Self::run_uni(session.clone(), subscriber) => res,
+				res = Self::run_bi(session.clone(), publisher, role) => res,
+				res = Self::run_uni(session.clone(), subscriber, role) => res,
 			};
 
 			if let Err(err) = res {
@@ -54,57 +83,55 @@ impl Session {
 
 	/// Perform the MoQ handshake as a client.
 	pub async fn connect<T: Into<web_transport::Session>>(session: T) -> Result<Self, Error> {
-		let mut session = session.into();
-		let mut stream = Stream::open(&mut session, message::ControlType::Session).await?;
-		Self::connect_setup(&mut stream).await.or_close(&mut stream)?;
-		Ok(Self::new(session, stream))
+		Self::build().connect(session).await
 	}
 
-	async fn connect_setup(setup: &mut Stream) -> Result<(), Error> {
-		let client = message::ClientSetup {
-			versions: [message::Version::CURRENT].into(),
-			extensions: Default::default(),
-		};
-
-		setup.writer.encode(&client).await?;
-		let server: message::ServerSetup = setup.reader.decode().await?;
-
-		tracing::info!(version = ?server.version, "connected");
-
-		Ok(())
+	/// Perform the MoQ handshake as a client, offering the given versions in preferred order.
+	pub async fn connect_versions<T: Into<web_transport::Session>>(
+		session: T,
+		versions: message::Versions,
+	) -> Result<Self, Error> {
+		Self::build().versions(versions).connect(session).await
 	}
 
 	/// Perform the MoQ handshake as a server
 	pub async fn accept<T: Into<web_transport::Session>>(session: T) -> Result<Self, Error> {
-		let mut session = session.into();
-		let mut stream = Stream::accept(&mut session).await?;
-		let kind = stream.reader.decode().await?;
-
-		if kind != message::ControlType::Session {
-			return Err(Error::UnexpectedStream(kind));
-		}
-
-		Self::accept_setup(&mut stream).await.or_close(&mut stream)?;
-		Ok(Self::new(session, stream))
+		Self::build().accept(session).await
 	}
 
-	async fn accept_setup(control: &mut Stream) -> Result<(), Error> {
-		let client: message::ClientSetup = control.reader.decode().await?;
+	/// Perform the MoQ handshake as a server, supporting only the given versions.
+	///
+	/// The client's preferred order is used to pick a version supported by both sides.
+	pub async fn accept_versions<T: Into<web_transport::Session>>(
+		session: T,
+		versions: message::Versions,
+	) -> Result<Self, Error> {
+		Self::build().versions(versions).accept(session).await
+	}
 
-		if !client.versions.contains(&message::Version::CURRENT) {
-			return Err(Error::Version(client.versions, [message::Version::CURRENT].into()));
-		}
+	/// Configure the handshake, ex. to send extensions, before connecting or accepting.
+	pub fn build() -> SessionBuilder {
+		SessionBuilder::new()
+	}
 
-		let server = message::ServerSetup {
-			version: message::Version::CURRENT,
-			extensions: Default::default(),
-		};
+	/// The version negotiated during the handshake.
+	pub fn version(&self) -> message::Version {
+		self.version
+	}
 
-		control.writer.encode(&server).await?;
+	/// The role negotiated during the handshake, based on what both sides support.
+	pub fn role(&self) -> message::Role {
+		self.role
+	}
 
-		tracing::info!(version = ?server.version, "connected");
+	/// The extensions sent by the peer during the handshake.
+	pub fn extensions(&self) -> &message::Extensions {
+		&self.extensions
+	}
 
-		Ok(())
+	/// A snapshot of the counters for each track and subscription, plus any transport statistics.
+	pub fn stats(&self) -> SessionStats {
+		self.stats.snapshot()
 	}
 
 	async fn run_session(mut stream: Stream) -> Result<(), Error> {
@@ -112,31 +139,47 @@ impl Session {
 		Err(Error::Cancel)
 	}
 
-	async fn run_uni(mut session: web_transport::Session, subscriber: Subscriber) -> Result<(), Error> {
+	async fn run_uni(
+		mut session: web_transport::Session,
+		subscriber: Subscriber,
+		role: message::Role,
+	) -> Result<(), Error> {
 		loop {
 			let mut stream = Reader::accept(&mut session).await?;
 			let subscriber = subscriber.clone();
 
 			spawn(async move {
-				Self::run_data(&mut stream, subscriber).await.or_close(&mut stream).ok();
+				Self::run_data(&mut stream, subscriber, role)
+					.await
+					.or_close(&mut stream)
+					.ok();
 			});
 		}
 	}
 
-	async fn run_data(stream: &mut Reader, mut subscriber: Subscriber) -> Result<(), Error> {
+	async fn run_data(stream: &mut Reader, mut subscriber: Subscriber, role: message::Role) -> Result<(), Error> {
+		// We never subscribed, so the peer can't send us any data.
+		if !role.is_subscriber() {
+			return Err(Error::RoleViolation(role));
+		}
+
 		let kind = stream.decode().await?;
 		match kind {
 			message::DataType::Group => subscriber.recv_group(stream).await,
 		}
 	}
 
-	async fn run_bi(mut session: web_transport::Session, publisher: Publisher) -> Result<(), Error> {
+	async fn run_bi(
+		mut session: web_transport::Session,
+		publisher: Publisher,
+		role: message::Role,
+	) -> Result<(), Error> {
 		loop {
 			let mut stream = Stream::accept(&mut session).await?;
 			let publisher = publisher.clone();
 
 			spawn(async move {
-				Self::run_control(&mut stream, publisher)
+				Self::run_control(&mut stream, publisher, role)
 					.await
 					.or_close(&mut stream)
 					.ok();
@@ -144,7 +187,12 @@ impl Session {
 		}
 	}
 
-	async fn run_control(stream: &mut Stream, mut publisher: Publisher) -> Result<(), Error> {
+	async fn run_control(stream: &mut Stream, mut publisher: Publisher, role: message::Role) -> Result<(), Error> {
+		// Every control stream is a request for the publisher.
+		if !role.is_publisher() {
+			return Err(Error::RoleViolation(role));
+		}
+
 		let kind = stream.reader.decode().await?;
 		match kind {
 			message::ControlType::Session => Err(Error::UnexpectedStream(kind)),
@@ -157,29 +205,111 @@ impl Session {
 
 	/// Publish a track, automatically announcing and serving it.
 	pub fn publish(&mut self, track: TrackConsumer) -> Result<(), Error> {
+		if !self.role.is_publisher() {
+			return Err(Error::RoleViolation(self.role));
+		}
+
 		self.publisher.publish(track)
 	}
 
 	/// Optionally announce the provided tracks.
 	/// This is advanced functionality if you wish to perform dynamic track generation in conjunction with [Self::route].
-	pub fn announce(&mut self, announced: AnnouncedConsumer) {
+	///
+	/// Returns [Error::RoleViolation] if the session can't publish.
+	pub fn announce(&mut self, announced: AnnouncedConsumer) -> Result<(), Error> {
+		if !self.role.is_publisher() {
+			return Err(Error::RoleViolation(self.role));
+		}
+
 		self.publisher.announce(announced);
+		Ok(())
 	}
 
 	/// Optionally route unknown paths.
 	/// This is advanced functionality if you wish to perform dynamic track generation in conjunction with [Self::announce].
-	pub fn route(&mut self, router: RouterConsumer) {
+	///
+	/// Returns [Error::RoleViolation] if the session can't publish.
+	pub fn route(&mut self, router: RouterConsumer) -> Result<(), Error> {
+		if !self.role.is_publisher() {
+			return Err(Error::RoleViolation(self.role));
+		}
+
 		self.publisher.route(router);
+		Ok(())
 	}
 
-	/// Subscribe to a track and start receiving data over the network.
+	/// Subscribe to a track and start receiving data over the network, starting at the latest group.
+	///
+	/// Subscriptions to the same live track are deduplicated, so any [TrackConsumer::update] applies to all of them.
+	///
+	/// The track is closed with [Error::RoleViolation] if the session can't subscribe.
 	pub fn subscribe(&self, track: Track) -> TrackConsumer {
-		self.subscriber.subscribe(track)
+		self.subscribe_request(track, TrackRequest::default())
+	}
+
+	/// Subscribe to a range of groups and/or with a drain policy, see [TrackRequest].
+	///
+	/// Subscriptions with a group range are never deduplicated.
+	pub fn subscribe_request(&self, track: Track, request: TrackRequest) -> TrackConsumer {
+		if !self.role.is_subscriber() {
+			let (producer, consumer) = track.produce();
+			producer.close(Error::RoleViolation(self.role));
+			return consumer;
+		}
+
+		self.subscriber.subscribe(track, request)
+	}
+
+	/// Subscribe to the live track on behalf of an existing [TrackProducer], returning once the subscription ends.
+	///
+	/// Unlike [Self::subscribe], the track is not deduplicated or closed on error.
+	/// This lets a relay subscribe to another origin without interrupting any consumers.
+	pub async fn subscribe_producer(&self, track: TrackProducer) -> Result<(), Error> {
+		if !self.role.is_subscriber() {
+			return Err(Error::RoleViolation(self.role));
+		}
+
+		self.subscriber.subscribe_producer(track).await
+	}
+
+	/// Request the info for a track, as determined by the remote publisher, without subscribing.
+	pub async fn info(&self, path: Path) -> Result<TrackInfo, Error> {
+		if !self.role.is_subscriber() {
+			return Err(Error::RoleViolation(self.role));
+		}
+
+		self.subscriber.info(path).await
+	}
+
+	/// Fetch a single group from a track, starting at the given byte offset.
+	///
+	/// Any frames before the offset are skipped, and the first frame will be truncated if the offset lands within it.
+	pub fn fetch(&self, track: Track, group: u64, offset: usize) -> GroupConsumer {
+		if !self.role.is_subscriber() {
+			let (producer, consumer) = Group::new(group).produce();
+			producer.close(Error::RoleViolation(self.role));
+			return consumer;
+		}
+
+		self.subscriber.fetch(track, group, offset)
 	}
 
 	/// Discover any tracks published by the remote matching a prefix.
 	pub fn announced(&self, prefix: Path) -> AnnouncedConsumer {
-		self.subscriber.announced(prefix)
+		self.announced_filter(AnnouncedFilter {
+			prefix,
+			..Default::default()
+		})
+	}
+
+	/// Discover any tracks published by the remote matching a filter, such as `room/*/camera` or a maximum depth.
+	pub fn announced_filter(&self, filter: AnnouncedFilter) -> AnnouncedConsumer {
+		if !self.role.is_subscriber() {
+			// Like a closed track, the consumer immediately returns None.
+			return AnnouncedProducer::new().subscribe_filter(filter);
+		}
+
+		self.subscriber.announced(filter)
 	}
 
 	/// Close the underlying WebTransport session.
@@ -200,3 +330,1061 @@ impl PartialEq for Session {
 }
 
 impl Eq for Session {}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::coding::{Decode, DecodeError, Encode};
+	use crate::{
+		loopback, Announced, AnnouncedProducer, Drain, GroupOrder, Router, TrackCache, TrackEvent, TrackLimits,
+		TrackRequest, TrackUpdate,
+	};
+	use bytes::Bytes;
+	use futures::FutureExt;
+
+	async fn connect() -> (Session, Session) {
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		(client.unwrap(), server.unwrap())
+	}
+
+	#[tokio::test]
+	async fn fetch() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		let mut group = track.create_group(7);
+		group.write_frame("hello");
+		group.write_frame("world");
+		drop(group);
+
+		let mut group = client.fetch(Track::new(Path::default().push("test")), 7, 0);
+		assert_eq!(group.sequence, 7);
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "world");
+		assert_eq!(group.read_frame().await.unwrap(), None);
+
+		// Fetches are included in the track totals on both sides.
+		let stats = client.stats().tracks[&Path::default().push("test")].clone();
+		assert_eq!(stats.groups_received, 1);
+		assert_eq!(stats.frames_received, 2);
+		assert_eq!(stats.bytes_received, 10);
+
+		let stats = server.stats().tracks[&Path::default().push("test")].clone();
+		assert_eq!(stats.groups_sent, 1);
+		assert_eq!(stats.frames_sent, 2);
+		assert_eq!(stats.bytes_sent, 10);
+	}
+
+	#[tokio::test]
+	async fn fetch_offset() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		let mut group = track.append_group();
+		group.write_frame("hello");
+		group.write_frame("world");
+		drop(group);
+
+		// Skip the first frame and part of the second.
+		let mut group = client.fetch(Track::new(Path::default().push("test")), 0, 7);
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "rld");
+		assert_eq!(group.read_frame().await.unwrap(), None);
+
+		// Skip the entire group.
+		let mut group = client.fetch(Track::new(Path::default().push("test")), 0, 10);
+		assert_eq!(group.read_frame().await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn announced_filter() {
+		let (client, mut server) = connect().await;
+
+		let room = Path::default().push("room");
+
+		let mut announced = AnnouncedProducer::new();
+		announced.announce(room.clone().push("alice").push("camera"));
+		announced.announce(room.clone().push("alice").push("camera").push("hd"));
+		announced.announce(room.clone().push("alice").push("screen"));
+		announced.announce(room.clone().push("bob").push("camera"));
+		server.announce(announced.subscribe()).unwrap();
+
+		let filter = AnnouncedFilter::new(room.clone().push("*").push("camera")).depth(2);
+		let mut consumer = client.announced_filter(filter);
+
+		// Collect everything until we're caught up.
+		let mut found = Vec::new();
+		loop {
+			match consumer.next().await.unwrap() {
+				Announced::Active(suffix, _) => found.push(suffix),
+				Announced::Live => break,
+				res => panic!("unexpected announcement: {:?}", res),
+			}
+		}
+
+		found.sort();
+		assert_eq!(
+			found,
+			vec![
+				Path::default().push("alice").push("camera"),
+				Path::default().push("bob").push("camera")
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn announced_metadata() {
+		let (client, mut server) = connect().await;
+
+		let path = Path::default().push("alice");
+
+		let mut announced = AnnouncedProducer::new();
+		announced.announce_with(path.clone(), Bytes::from_static(b"Alice"));
+		server.announce(announced.subscribe()).unwrap();
+
+		let mut consumer = client.announced(Path::default());
+		assert_eq!(
+			consumer.next().await,
+			Some(Announced::Active(path.clone(), Bytes::from_static(b"Alice")))
+		);
+
+		// Updates to the metadata are announced again.
+		announced.announce_with(path.clone(), Bytes::from_static(b"Alice (away)"));
+		loop {
+			match consumer.next().await.unwrap() {
+				Announced::Active(active, metadata) => {
+					assert_eq!(active, path);
+					assert_eq!(metadata, Bytes::from_static(b"Alice (away)"));
+					break;
+				}
+				Announced::Live => continue,
+				res => panic!("unexpected announcement: {:?}", res),
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn announced_metadata_older() {
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::connect_versions(client, [message::Version::FORK_07].into()),
+			Session::accept(server)
+		);
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let alice = Path::default().push("alice");
+		let bob = Path::default().push("bob");
+
+		let mut announced = AnnouncedProducer::new();
+		announced.announce_with(alice.clone(), Bytes::from_static(b"Alice"));
+		server.announce(announced.subscribe()).unwrap();
+
+		// The metadata can't be sent to older versions.
+		let mut consumer = client.announced(Path::default());
+		assert_eq!(
+			consumer.next().await,
+			Some(Announced::Active(alice.clone(), Bytes::new()))
+		);
+		assert_eq!(consumer.next().await, Some(Announced::Live));
+
+		// Updating the metadata isn't announced again, which older versions would treat as a duplicate.
+		announced.announce_with(alice.clone(), Bytes::from_static(b"Alice (away)"));
+		announced.announce(bob.clone());
+		assert_eq!(
+			consumer.next().await,
+			Some(Announced::Active(bob.clone(), Bytes::new()))
+		);
+
+		// Announcing again after ending is still allowed.
+		announced.unannounce(&alice);
+		assert_eq!(consumer.next().await, Some(Announced::Ended(alice.clone())));
+		announced.announce(alice.clone());
+		assert_eq!(consumer.next().await, Some(Announced::Active(alice, Bytes::new())));
+	}
+
+	#[tokio::test]
+	async fn fetch_not_found() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+		track.append_group().write_frame("hello");
+
+		let mut group = client.fetch(Track::new(Path::default().push("test")), 1, 0);
+		assert!(group.read_frame().await.is_err());
+
+		let mut group = client.fetch(Track::new(Path::default().push("missing")), 0, 0);
+		assert!(group.read_frame().await.is_err());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn subscribe_range() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		for _ in 0..5 {
+			track.append_group().write_frame("hello");
+		}
+
+		let consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(1..=3),
+		);
+		consumer.closed().await.unwrap();
+		assert_eq!(consumer.cached_groups(), vec![1, 2, 3]);
+
+		// Wait for groups that don't exist yet.
+		// Time is paused, so the sleep only returns once the subscription is idle.
+		let consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(3..=6),
+		);
+
+		tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		track.append_group().write_frame("hello");
+		track.append_group().write_frame("hello");
+		track.append_group().write_frame("hello");
+
+		consumer.closed().await.unwrap();
+		assert_eq!(consumer.cached_groups(), vec![3, 4, 5, 6]);
+	}
+
+	#[tokio::test]
+	async fn subscribe_range_gap() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		track.create_group(0);
+		track.create_group(1);
+		track.create_group(2);
+		track.create_group(5);
+
+		// Groups 3 and 4 will never exist, so the publisher reports them as dropped.
+		let consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(1..=4),
+		);
+		consumer.closed().await.unwrap();
+		assert_eq!(consumer.cached_groups(), vec![1, 2]);
+	}
+
+	#[tokio::test]
+	async fn subscribe_range_huge() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		track.create_group(1);
+		track.create_group(2);
+
+		// The track ends early, so the publisher drops the rest of the huge range in a single message.
+		let consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(1..=1 << 60),
+		);
+		consumer.info().await.unwrap();
+		drop(track);
+
+		tokio::time::timeout(std::time::Duration::from_secs(1), consumer.closed())
+			.await
+			.expect("timed out")
+			.unwrap();
+		assert_eq!(consumer.cached_groups(), vec![1, 2]);
+	}
+
+	#[tokio::test]
+	async fn subscribe_open_range() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		for _ in 0..5 {
+			track.append_group();
+		}
+
+		let mut consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(2..),
+		);
+
+		// Wait until we've received the latest group, which may be delivered before the others.
+		while consumer.next_group().await.unwrap().unwrap().sequence != 4 {}
+
+		track.append_group();
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 5);
+
+		let mut cached = consumer.cached_groups();
+		cached.sort();
+		assert_eq!(cached, vec![2, 3, 4, 5]);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn subscribe_update() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		track.append_group();
+
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+
+		// Bound the subscription while it's live, so it ends after group 2.
+		consumer.update(TrackUpdate {
+			priority: 1,
+			order: GroupOrder::Desc,
+			group_min: None,
+			group_max: Some(2),
+		});
+
+		// Returns once the update has been applied, since time only advances when every task is idle.
+		tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		track.append_group();
+		track.append_group();
+		track.append_group();
+
+		consumer.closed().await.unwrap();
+		assert!(consumer.cached_groups().iter().all(|&sequence| sequence <= 2));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn subscribe_update_shared() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		track.append_group();
+
+		// Both consumers share a single subscription.
+		let mut first = client.subscribe(Track::new(Path::default().push("test")));
+		let second = client.subscribe(Track::new(Path::default().push("test")));
+		assert_eq!(first.next_group().await.unwrap().unwrap().sequence, 0);
+
+		// Bounding one of them bounds the other.
+		second.update(TrackUpdate {
+			priority: 0,
+			order: GroupOrder::Desc,
+			group_min: None,
+			group_max: Some(1),
+		});
+
+		// Wait until the update is applied (time is paused).
+		tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		track.append_group();
+		track.append_group();
+
+		first.closed().await.unwrap();
+		assert!(first.cached_groups().iter().all(|&sequence| sequence <= 1));
+	}
+
+	#[tokio::test]
+	async fn info() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::build()
+			.path("test")
+			.priority(3)
+			.group_order(GroupOrder::Asc)
+			.produce();
+		server.publish(reader).unwrap();
+
+		track.append_group();
+		track.append_group();
+
+		let info = client.info(Path::default().push("test")).await.unwrap();
+		assert_eq!(info.priority, 3);
+		assert_eq!(info.order, GroupOrder::Asc);
+		assert_eq!(info.group_latest, 1);
+
+		// The subscriber uses the publisher's info, not the requested values.
+		let consumer = client.subscribe(Track::new(Path::default().push("test")));
+		let info = consumer.info().await.unwrap();
+		assert_eq!(info.priority, 3);
+		assert_eq!(info.order, GroupOrder::Asc);
+		assert_eq!(info.group_latest, 1);
+
+		// The consumer reports the publisher's values once known.
+		assert_eq!(consumer.priority, 3);
+		assert_eq!(consumer.order, GroupOrder::Asc);
+
+		assert!(client.info(Path::default().push("unknown")).await.is_err());
+		assert!(client
+			.subscribe(Track::new(Path::default().push("unknown")))
+			.info()
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn subscribe_pending_info() {
+		let (client, mut server) = connect().await;
+
+		// Simulate a relay that's still waiting for the upstream publisher.
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		track.clear_info();
+		server.publish(reader).unwrap();
+
+		// Groups are served without waiting for the info.
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		track.append_group();
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+		assert!(consumer.info().now_or_never().is_none());
+
+		track.set_info(TrackInfo {
+			priority: 2,
+			order: GroupOrder::Asc,
+			group_latest: 0,
+		});
+
+		let info = consumer.info().await.unwrap();
+		assert_eq!(info.priority, 2);
+		assert_eq!(consumer.priority, 2);
+	}
+
+	#[tokio::test]
+	async fn subscribe_events() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		track.create_group(1);
+		track.create_group(4);
+
+		// Groups 2 and 3 will never exist, so the publisher reports them as dropped.
+		let mut consumer = client.subscribe_request(
+			Track::build().path("test").cache(cache).into(),
+			TrackRequest::default().groups(1..=3),
+		);
+
+		let mut groups = Vec::new();
+		let mut gaps = Vec::new();
+
+		while let Some(event) = consumer.next_event().await.unwrap() {
+			match event {
+				TrackEvent::Group(group) => groups.push(group.sequence),
+				TrackEvent::Gap { from, to } => gaps.push((from, to)),
+				TrackEvent::Reset { .. } => unreachable!(),
+			}
+		}
+
+		assert_eq!(groups, vec![1]);
+		assert_eq!(gaps, vec![(2, 3)]);
+
+		// A group reset by the publisher is reported after the group itself.
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		let mut group = track.append_group();
+		group.write_frame("hello");
+
+		loop {
+			match consumer.next_event().await.unwrap().unwrap() {
+				TrackEvent::Group(group) if group.sequence == 5 => break,
+				_ => continue,
+			}
+		}
+
+		group.close(Error::App(1));
+
+		loop {
+			match consumer.next_event().await.unwrap().unwrap() {
+				TrackEvent::Reset { sequence, .. } => break assert_eq!(sequence, 5),
+				_ => continue,
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn version() {
+		let (client, server) = connect().await;
+		assert_eq!(client.version(), message::Version::CURRENT);
+		assert_eq!(server.version(), message::Version::CURRENT);
+
+		// An older client negotiates the older version.
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::connect_versions(client, [message::Version::FORK_04].into()),
+			Session::accept(server)
+		);
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+		assert_eq!(client.version(), message::Version::FORK_04);
+		assert_eq!(server.version(), message::Version::FORK_04);
+
+		// Make sure version-specific messages are still understood.
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+		track.append_group();
+
+		let consumer = client.subscribe(Track::new(Path::default().push("test")));
+		consumer.info().await.unwrap();
+
+		consumer.update(TrackUpdate {
+			priority: -1,
+			order: GroupOrder::Asc,
+			group_min: None,
+			group_max: Some(0),
+		});
+		consumer.closed().await.unwrap();
+
+		// No common version.
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::build().versions([message::Version::FORK_03]).connect(client),
+			Session::accept_versions(server, message::Version::SUPPORTED.into())
+		);
+		assert!(client.is_err());
+		assert!(matches!(server, Err(Error::Version(..))));
+	}
+
+	#[derive(Debug, PartialEq)]
+	struct Token(String);
+
+	impl Encode for Token {
+		fn encode<W: bytes::BufMut>(&self, w: &mut W) {
+			self.0.encode(w)
+		}
+	}
+
+	impl Decode for Token {
+		fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+			Ok(Self(String::decode(r)?))
+		}
+	}
+
+	impl message::Extension for Token {
+		fn id() -> u64 {
+			0xabcd
+		}
+	}
+
+	#[tokio::test]
+	async fn extensions() {
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::build().extension(Token("client".into())).connect(client),
+			Session::build().extension(Token("server".into())).accept(server)
+		);
+		let (client, server) = (client.unwrap(), server.unwrap());
+
+		assert_eq!(
+			client.extensions().get::<Token>().unwrap(),
+			Some(Token("server".into()))
+		);
+		assert_eq!(
+			server.extensions().get::<Token>().unwrap(),
+			Some(Token("client".into()))
+		);
+
+		// Unknown extensions are not sent by default.
+		let (client, server) = connect().await;
+		assert_eq!(client.extensions().get::<Token>().unwrap(), None);
+		assert_eq!(server.extensions().get::<Token>().unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn role() {
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::build().role(message::Role::Subscriber).connect(client),
+			Session::build().accept(server)
+		);
+		let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+		// The server supports both, but the client can only subscribe.
+		assert_eq!(client.role(), message::Role::Subscriber);
+		assert_eq!(server.role(), message::Role::Publisher);
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+		track.append_group();
+
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+
+		let (_track, reader) = Track::new(Path::default().push("test")).produce();
+		assert!(matches!(client.publish(reader), Err(Error::RoleViolation(_))));
+
+		let consumer = server.subscribe(Track::new(Path::default().push("test")));
+		assert!(matches!(consumer.closed().await, Err(Error::RoleViolation(_))));
+
+		// The same goes for announcements and routing.
+		let announced = AnnouncedProducer::new();
+		assert!(matches!(
+			client.announce(announced.subscribe()),
+			Err(Error::RoleViolation(_))
+		));
+
+		let (_router, router) = Router { capacity: 1 }.produce();
+		assert!(matches!(client.route(router), Err(Error::RoleViolation(_))));
+
+		let mut announced = server.announced(Path::default());
+		assert!(announced.next().await.is_none());
+
+		// Nobody is subscribing.
+		let (client, server) = loopback::connect().await;
+		let (client, server) = tokio::join!(
+			Session::build().role(message::Role::Publisher).connect(client),
+			Session::build().role(message::Role::Publisher).accept(server)
+		);
+		assert!(client.is_err());
+		assert!(matches!(server, Err(Error::RoleIncompatible(..))));
+	}
+
+	// Paused time skips ahead to each retransmission timer instead of waiting for it.
+	#[tokio::test(start_paused = true)]
+	async fn loopback_loss() {
+		let config = loopback::Config {
+			delay: std::time::Duration::from_millis(2),
+			loss: 0.05,
+			..Default::default()
+		};
+
+		let (client, server) = loopback::connect_with(config).await;
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		let mut group = track.append_group();
+		for _ in 0..100 {
+			group.write_frame(vec![0u8; 1000]);
+		}
+		drop(group);
+
+		// QUIC retransmits anything that was lost.
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		let mut group = consumer.next_group().await.unwrap().unwrap();
+
+		let mut count = 0;
+		while let Some(frame) = group.read_frame().await.unwrap() {
+			assert_eq!(frame.len(), 1000);
+			count += 1;
+		}
+		assert_eq!(count, 100);
+	}
+
+	#[tokio::test]
+	async fn loopback_reset() {
+		let config = loopback::Config {
+			reset: 1.0,
+			reset_after: 10_000,
+			reset_code: 42,
+			..Default::default()
+		};
+
+		let (client, server) = loopback::connect_with(config).await;
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		let mut consumer = client.subscribe(Track::new(Path::default().push("test")));
+		consumer.info().await.unwrap();
+
+		let mut group = track.append_group();
+		for _ in 0..100 {
+			group.write_frame(vec![0u8; 1000]);
+		}
+
+		// Every group stream is reset by the transport, partway through.
+		loop {
+			match consumer.next_event().await.unwrap().unwrap() {
+				TrackEvent::Reset { sequence, .. } => break assert_eq!(sequence, 0),
+				_ => continue,
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn group_limit() {
+		let (client, mut server) = connect().await;
+
+		let (mut track, reader) = Track::new(Path::default().push("test")).produce();
+		server.publish(reader).unwrap();
+
+		let limits = TrackLimits {
+			group: Some(1000),
+			..Default::default()
+		};
+		let mut consumer = client.subscribe(Track::build().path("test").limits(limits).into());
+		consumer.info().await.unwrap();
+
+		let mut group = track.append_group();
+		for _ in 0..10 {
+			group.write_frame(vec![0u8; 500]);
+		}
+
+		// The subscriber stops reading the group once it exceeds the limit.
+		loop {
+			match consumer.next_event().await.unwrap().unwrap() {
+				TrackEvent::Reset { sequence, code } => {
+					assert_eq!(sequence, 0);
+					assert_eq!(code, Error::TooLarge.to_code());
+					break;
+				}
+				_ => continue,
+			}
+		}
+
+		assert!(consumer.buffered() <= 1000);
+	}
+
+	// Paused time only advances once every task is idle, so sleeping waits until the groups are queued.
+	#[tokio::test(start_paused = true)]
+	async fn scheduler_congestion() {
+		// Only allow a single group stream at a time.
+		let config = loopback::Config {
+			max_streams: 1,
+			..Default::default()
+		};
+
+		let (client, server) = loopback::connect_with(config).await;
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build()
+			.path("test")
+			.cache(cache)
+			.group_order(GroupOrder::Desc)
+			.produce();
+		server.publish(reader).unwrap();
+
+		let mut consumer = client.subscribe(Track::build().path("test").group_order(GroupOrder::Desc).into());
+		consumer.info().await.unwrap();
+
+		// The first group holds the only stream until it's finished.
+		let mut first = track.append_group();
+		first.write_frame("hello");
+		assert_eq!(consumer.next_group().await.unwrap().unwrap().sequence, 0);
+
+		// Queue up more groups, keeping them open so only one is delivered at a time.
+		let mut groups = Vec::new();
+		for _ in 0..5 {
+			let mut group = track.append_group();
+			group.write_frame("hello");
+			groups.push(group);
+		}
+
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		drop(first);
+
+		// The newest group gets the credit first.
+		let group = consumer.next_group().await.unwrap().unwrap();
+		assert_eq!(group.sequence, 5);
+	}
+
+	// Tracks with the same priority take turns when streams are limited, so one can't starve the other.
+	#[tokio::test(start_paused = true)]
+	async fn scheduler_round_robin() {
+		// Only allow a single group stream at a time.
+		let config = loopback::Config {
+			max_streams: 1,
+			..Default::default()
+		};
+
+		let (client, server) = loopback::connect_with(config).await;
+		let (client, server) = tokio::join!(
+			Session::connect(client),
+			Session::build().fairness(Fairness::RoundRobin).accept(server)
+		);
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let mut tracks = Vec::new();
+		let mut consumers = Vec::new();
+
+		for path in ["a", "b"] {
+			let (track, reader) = Track::build()
+				.path(path)
+				.cache(cache)
+				.group_order(GroupOrder::Asc)
+				.produce();
+			server.publish(reader).unwrap();
+			tracks.push(track);
+
+			let consumer = client.subscribe(Track::build().path(path).group_order(GroupOrder::Asc).into());
+			consumer.info().await.unwrap();
+			consumers.push(consumer);
+		}
+
+		// The first group of "a" holds the only stream until it's finished.
+		let mut first = tracks[0].append_group();
+		first.write_frame("hello");
+		assert_eq!(consumers[0].next_group().await.unwrap().unwrap().sequence, 0);
+
+		// Queue up three groups for "a" before two groups for "b", keeping them open so only one is delivered at a time.
+		let mut groups = [Vec::new(), Vec::new()];
+		for (index, count) in [(0, 3), (1, 2)] {
+			for _ in 0..count {
+				let mut group = tracks[index].append_group();
+				group.write_frame("hello");
+				groups[index].push(group);
+			}
+		}
+
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+		drop(first);
+
+		let mut order = Vec::new();
+
+		for _ in 0..5 {
+			let (a, b) = consumers.split_at_mut(1);
+			let (index, group) = tokio::select! {
+				res = a[0].next_group() => (0, res.unwrap().unwrap()),
+				res = b[0].next_group() => (1, res.unwrap().unwrap()),
+			};
+
+			order.push((["a", "b"][index], group.sequence));
+
+			// Finish the group so the next one can get the stream.
+			groups[index].retain(|pending| pending.sequence != group.sequence);
+		}
+
+		// The tracks alternate instead of "a" sending every queued group first.
+		assert_eq!(order, vec![("a", 1), ("b", 0), ("a", 2), ("b", 1), ("a", 3)]);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn subscribe_drain() {
+		let (client, mut server) = connect().await;
+
+		let (mut current, reader) = Track::build().path("current").produce();
+		server.publish(reader).unwrap();
+
+		let (mut reset, reader) = Track::build().path("reset").produce();
+		server.publish(reader).unwrap();
+
+		let mut groups = Vec::new();
+
+		for (track, drain) in [(&mut current, Drain::Current), (&mut reset, Drain::Reset)] {
+			let request = TrackRequest::default().drain(drain);
+			let mut consumer = client.subscribe_request(Track::new(track.path.clone()), request);
+			consumer.info().await.unwrap();
+
+			let mut group = track.append_group();
+			group.write_frame("hello");
+
+			// Keep reading the group after cancelling the subscription.
+			let mut reader = consumer.next_group().await.unwrap().unwrap();
+			assert_eq!(reader.read_frame().await.unwrap().unwrap(), "hello");
+			drop(consumer);
+
+			groups.push((group, reader));
+		}
+
+		// Give the publisher a chance to notice; time is paused so this waits until it's idle.
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+		for (group, _) in groups.iter_mut() {
+			group.write_frame("world");
+		}
+
+		let (_, mut finished) = groups.remove(0);
+		let (_, mut cancelled) = groups.remove(0);
+
+		// The current group is finished.
+		assert_eq!(finished.read_frame().await.unwrap().unwrap(), "world");
+		assert!(finished.read_frame().await.unwrap().is_none());
+
+		// But the other subscription is reset immediately.
+		assert!(cancelled.read_frame().await.is_err());
+	}
+
+	#[tokio::test]
+	async fn subscribe_range_drain() {
+		let (client, mut server) = connect().await;
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		track.append_group().write_frame("hello");
+		let mut group = track.append_group();
+		group.write_frame("hello");
+
+		// Every group in the range has started, but the last one isn't finished yet.
+		let request = TrackRequest::default().groups(0..=1).drain(Drain::Reset);
+		let mut consumer = client.subscribe_request(Track::build().path("test").cache(cache).into(), request);
+
+		let mut reader = loop {
+			let reader = consumer.next_group().await.unwrap().unwrap();
+			if reader.sequence == 1 {
+				break reader;
+			}
+		};
+		assert_eq!(reader.read_frame().await.unwrap().unwrap(), "hello");
+
+		// The subscription isn't cancelled until the group is done, so it's not reset.
+		group.write_frame("world");
+		drop(group);
+
+		assert_eq!(reader.read_frame().await.unwrap().unwrap(), "world");
+		assert!(reader.read_frame().await.unwrap().is_none());
+		consumer.closed().await.unwrap();
+	}
+
+	// Paused time only advances once every task is idle, so sleeping waits for the publisher to catch up.
+	#[tokio::test(start_paused = true)]
+	async fn subscribe_drain_pending() {
+		// Only allow a single group stream at a time.
+		let config = loopback::Config {
+			max_streams: 1,
+			..Default::default()
+		};
+
+		let (client, server) = loopback::connect_with(config).await;
+		let (client, server) = tokio::join!(Session::connect(client), Session::accept(server));
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let cache = TrackCache {
+			groups: 10,
+			..Default::default()
+		};
+
+		let (mut track, reader) = Track::build().path("test").cache(cache).produce();
+		server.publish(reader).unwrap();
+
+		let mut consumer = client.subscribe_request(
+			Track::new(Path::default().push("test")),
+			TrackRequest::default().drain(Drain::Finish),
+		);
+		consumer.info().await.unwrap();
+
+		let mut first = track.append_group();
+		first.write_frame("hello");
+
+		let mut reader = consumer.next_group().await.unwrap().unwrap();
+		assert_eq!(reader.read_frame().await.unwrap().unwrap(), "hello");
+
+		// The second group waits for the stream used by the first group.
+		track.append_group().write_frame("world");
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+		// Cancel the subscription, which finishes the first group but never starts the second.
+		drop(consumer);
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+		drop(first);
+		assert!(reader.read_frame().await.unwrap().is_none());
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+		let stats = server.stats();
+		assert_eq!(stats.tracks[&Path::default().push("test")].groups_sent, 1);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn stats() {
+		let (client_session, server) = loopback::connect().await;
+
+		let rtt = std::time::Duration::from_millis(42);
+		let client = Session::build().transport(move || TransportStats {
+			rtt: Some(rtt),
+			cwnd: None,
+		});
+
+		let (client, server) = tokio::join!(client.connect(client_session), Session::accept(server));
+		let (client, mut server) = (client.unwrap(), server.unwrap());
+
+		let path = Path::default().push("test");
+		let (mut track, reader) = Track::new(path.clone()).produce();
+		server.publish(reader).unwrap();
+
+		let mut announced = client.announced(Path::default());
+		announced.next().await.unwrap();
+
+		let mut consumer = client.subscribe(Track::new(path.clone()));
+		consumer.info().await.unwrap();
+
+		let mut group = track.append_group();
+		group.write_frame("hello");
+		group.write_frame("world");
+		drop(group);
+
+		let mut group = consumer.next_group().await.unwrap().unwrap();
+		while group.read_frame().await.unwrap().is_some() {}
+
+		let stats = client.stats();
+		assert_eq!(stats.announces_received, 1);
+		assert_eq!(stats.transport.rtt, Some(rtt));
+		assert_eq!(stats.subscribing.len(), 1);
+
+		let subscription = &stats.subscribing[0];
+		assert_eq!(subscription.path, path);
+		assert_eq!(subscription.stats.groups_received, 1);
+		assert_eq!(subscription.stats.frames_received, 2);
+		assert_eq!(subscription.stats.bytes_received, 10);
+
+		let stats = server.stats();
+		assert_eq!(stats.announces_sent, 1);
+		assert_eq!(stats.transport, TransportStats::default());
+
+		let track = &stats.tracks[&path];
+		assert_eq!(track.groups_sent, 1);
+		assert_eq!(track.frames_sent, 2);
+		assert_eq!(track.bytes_sent, 10);
+		assert_eq!(track.resets_sent, 0);
+
+		// The totals are kept after the subscription ends.
+		drop(consumer);
+		drop(group);
+
+		// Time is paused, so this waits until the subscription has been cleaned up.
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+		let stats = client.stats();
+		assert!(stats.subscribing.is_empty());
+		assert_eq!(stats.tracks[&path].frames_received, 2);
+		assert_eq!(stats.tracks[&path].streams, 0);
+	}
+}
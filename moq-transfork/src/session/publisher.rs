@@ -1,33 +1,45 @@
-use std::collections::{hash_map, HashMap};
+use std::{
+	collections::{hash_map, BTreeSet, HashMap, HashSet},
+	sync::Arc,
+};
 
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio::sync::watch;
 
 use crate::{
 	message,
-	model::{GroupConsumer, Track, TrackConsumer},
-	Announced, AnnouncedConsumer, AnnouncedProducer, Error, GroupOrder, Path, RouterConsumer,
+	model::{GroupConsumer, Track, TrackConsumer, TrackInfo},
+	Announced, AnnouncedConsumer, AnnouncedFilter, AnnouncedProducer, Error, GroupOrder, Path, RouterConsumer,
 };
 
-use moq_async::{spawn, FuturesExt, Lock, OrClose};
+use moq_async::{spawn, Lock, OrClose};
 
-use super::{Stream, Writer};
+use super::{Counters, Fairness, Scheduler, Stats, Stream, Writer};
 
 #[derive(Clone)]
 pub(super) struct Publisher {
 	session: web_transport::Session,
+	scheduler: Scheduler,
+	version: message::Version,
+	fairness: Fairness,
+	stats: Stats,
 	announced: AnnouncedProducer,
 	tracks: Lock<HashMap<Path, TrackConsumer>>,
 	router: Lock<Option<RouterConsumer>>,
 }
 
 impl Publisher {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, version: message::Version, fairness: Fairness, stats: Stats) -> Self {
 		// We start the publisher in live mode because we're producing content.
 		let mut announced = AnnouncedProducer::new();
 		announced.live();
 
 		Self {
+			scheduler: Scheduler::new(session.clone()),
 			session,
+			version,
+			fairness,
+			stats,
 			announced,
 			tracks: Default::default(),
 			router: Default::default(),
@@ -69,7 +81,7 @@ impl Publisher {
 		spawn(async move {
 			while let Some(announced) = announced.next().await {
 				match announced {
-					Announced::Active(path) => downstream.announce(path.clone()),
+					Announced::Active(path, metadata) => downstream.announce_with(path, metadata),
 					Announced::Ended(path) => downstream.unannounce(&path),
 
 					// Indicate that we're caught up to live.
@@ -88,27 +100,49 @@ impl Publisher {
 	}
 
 	pub async fn recv_announce(&mut self, stream: &mut Stream) -> Result<(), Error> {
-		let interest = stream.reader.decode::<message::AnnouncePlease>().await?;
+		let interest: message::AnnouncePlease = stream.reader.decode_version(self.version).await?;
 		let prefix = interest.prefix;
-		tracing::debug!(?prefix, "announce interest");
+		tracing::debug!(?prefix, pattern = ?interest.pattern, depth = ?interest.depth, "announce interest");
+
+		let mut announced = self.announced.subscribe_filter(AnnouncedFilter {
+			prefix: prefix.clone(),
+			pattern: interest.pattern,
+			depth: interest.depth.map(|depth| depth.try_into().unwrap_or(usize::MAX)),
+		});
 
-		let mut announced = self.announced.subscribe_prefix(prefix.clone());
+		// Older versions can't update the metadata, and treat a repeated announce as a duplicate.
+		let updates = self.version >= message::Version::FORK_08;
+		let mut active = HashSet::new();
 
 		// Flush any synchronously announced paths
 		while let Some(announced) = announced.next().await {
 			match announced {
-				Announced::Active(suffix) => {
+				Announced::Active(suffix, metadata) => {
+					if !active.insert(suffix.clone()) && !updates {
+						continue;
+					}
+
 					tracing::debug!(?prefix, ?suffix, "announce");
-					stream.writer.encode(&message::Announce::Active { suffix }).await?;
+					let announce = message::Announce::Active { suffix, metadata };
+					stream.writer.encode_version(&announce, self.version).await?;
+					self.stats.announce_sent();
 				}
 				Announced::Ended(suffix) => {
+					active.remove(&suffix);
+
 					tracing::debug!(?prefix, ?suffix, "unannounce");
-					stream.writer.encode(&message::Announce::Ended { suffix }).await?;
+					stream
+						.writer
+						.encode_version(&message::Announce::Ended { suffix }, self.version)
+						.await?;
 				}
 				Announced::Live => {
 					// Indicate that we're caught up to live.
 					tracing::debug!(?prefix, "live");
-					stream.writer.encode(&message::Announce::Live).await?;
+					stream
+						.writer
+						.encode_version(&message::Announce::Live, self.version)
+						.await?;
 				}
 			}
 		}
@@ -119,60 +153,174 @@ impl Publisher {
 	}
 
 	pub async fn recv_subscribe(&mut self, stream: &mut Stream) -> Result<(), Error> {
-		let subscribe = stream.reader.decode().await?;
+		let subscribe = stream.reader.decode_version(self.version).await?;
 		self.serve_subscribe(stream, subscribe).await
 	}
 
 	#[tracing::instrument("publishing", skip_all, err, fields(track = ?subscribe.path, id = subscribe.id))]
 	async fn serve_subscribe(&mut self, stream: &mut Stream, subscribe: message::Subscribe) -> Result<(), Error> {
+		let counters = self.stats.publishing(subscribe.id, subscribe.path.clone());
+
 		let track = Track {
 			path: subscribe.path,
 			priority: subscribe.priority,
 			order: subscribe.group_order,
+			..Default::default()
 		};
 
 		let mut track = self.get_track(track).await?;
 
-		let info = message::Info {
-			group_latest: track.latest_group(),
-			group_order: track.order,
-			track_priority: track.priority,
+		// The info requires a round trip to the upstream publisher when relaying, so don't block on it.
+		// The only exception is a bounded range without a minimum, which starts at the latest group.
+		let mut pending = {
+			let track = track.clone();
+			Box::pin(async move { track.info().await })
 		};
 
-		tracing::info!(?info, "active");
+		let info = match subscribe.group_min {
+			None if subscribe.group_max.is_some() => Some(pending.as_mut().await?),
+			_ => pending.as_mut().now_or_never().transpose()?,
+		};
 
-		stream.writer.encode(&info).await?;
+		if let Some(info) = &info {
+			Self::send_info(stream, info).await?;
+		}
+
+		// The subscriber can override the priority and order via an update, which applies to any open groups.
+		// Until the info is known, we use the requested priority and order.
+		let (priority, priorities) = watch::channel(Priority {
+			track: info.as_ref().map_or(subscribe.priority, |info| info.priority),
+			order: info.as_ref().map_or(subscribe.group_order, |info| info.order),
+			fairness: self.fairness,
+			oldest: 0,
+			newest: 0,
+		});
+
+		// Used to reset groups in flight when the subscription is cancelled.
+		let (cancel, cancels) = watch::channel(Cancel::None);
+
+		let serve = {
+			let scheduler = self.scheduler.clone();
+
+			move |mut group: GroupConsumer| {
+				let scheduler = scheduler.clone();
+				let priority = priorities.clone();
+				let cancel = cancels.clone();
+				let counters = counters.clone();
+
+				async move {
+					let res = Self::serve_group(scheduler, subscribe.id, priority, cancel, counters, &mut group).await;
+					(group, res)
+				}
+			}
+		};
 
 		let mut tasks = FuturesUnordered::new();
 		let mut complete = false;
 
+		// The groups currently being served, used to rank them for round-robin.
+		let mut serving = BTreeSet::new();
+
+		// Start at the latest group unless a minimum was requested.
+		let latest = info
+			.as_ref()
+			.map_or_else(|| track.latest_group(), |info| info.group_latest);
+		let start = subscribe.group_min.unwrap_or(latest);
+		let mut range = Range::new(start, subscribe.group_max);
+
+		// Set once the info has been sent.
+		let mut active = info.is_some();
+
+		// Serve any cached groups within the range first, in ascending order.
+		for sequence in track.cached_groups() {
+			if let Ok(group) = track.get_group(sequence) {
+				if range.insert(sequence) {
+					serving.insert(sequence);
+					tasks.push(serve(group));
+				}
+			}
+		}
+
 		loop {
+			// Update the range of groups being served before any new tasks are polled.
+			if let (Some(&oldest), Some(&newest)) = (serving.first(), serving.last()) {
+				priority.send_if_modified(|priority| priority.serving(oldest, newest));
+			}
+
 			tokio::select! {
-				Some(group) = track.next_group().transpose() => {
-					let mut group = group?;
-					let session = self.session.clone();
-					let priority = Self::stream_priority(track.priority, track.order, group.sequence);
-
-					tasks.push(async move {
-						let res = Self::serve_group(session, subscribe.id, priority, &mut group).await;
-						(group, res)
+				res = pending.as_mut(), if !active && !complete => {
+					let info = res?;
+					Self::send_info(stream, &info).await?;
+
+					// The subscriber only sends updates after receiving the info, so there's nothing to override.
+					priority.send_modify(|priority| {
+						priority.track = info.priority;
+						priority.order = info.order;
 					});
+
+					active = true;
+				},
+				res = track.next_group(), if !range.done => match res? {
+					Some(group) => {
+						// Serve any cached groups we skipped over, since only the latest group is returned.
+						for sequence in track.cached_groups().into_iter().filter(|&sequence| sequence < group.sequence) {
+							if let Ok(group) = track.get_group(sequence) {
+								if range.insert(sequence) {
+									serving.insert(sequence);
+									tasks.push(serve(group));
+								}
+							}
+						}
+
+						if range.insert(group.sequence) {
+							serving.insert(group.sequence);
+							tasks.push(serve(group));
+						}
+					},
+					// The track has ended
+					None => range.close(),
 				},
-				res = stream.reader.decode_maybe::<message::SubscribeUpdate>(), if !complete => match res? {
-					Some(_update) => {
-						// TODO use it
+				res = stream.reader.decode_maybe_version::<message::SubscribeUpdate>(self.version), if !complete => match res? {
+					Some(update) => {
+						tracing::info!(?update, "updated");
+
+						priority.send_modify(|priority| {
+							priority.track = update.priority;
+							priority.order = update.group_order;
+						});
+						range.update(update.group_min, update.group_max);
 					},
-					// Subscribe has completed
+					// The subscriber is no longer interested, so stop serving new groups and drain the rest.
 					None => {
 						complete = true;
+						range.close();
+
+						let current = match priority.borrow().order {
+							GroupOrder::Asc => serving.first(),
+							GroupOrder::Desc => serving.last(),
+						};
+
+						tracing::debug!(drain = ?subscribe.drain, ?current, "cancelled");
+
+						cancel.send_replace(match (subscribe.drain, current) {
+							(message::Drain::Finish, _) => Cancel::Pending,
+							(message::Drain::Current, Some(&current)) => Cancel::Except(current),
+							(message::Drain::Current, None) | (message::Drain::Reset, _) => Cancel::All,
+						});
 					}
 				},
 				Some(res) = tasks.next() => {
 					let (group, res) = res;
+					serving.remove(&group.sequence);
 
 					if let Err(err) = res {
 						tracing::warn!(?err, subscribe = ?subscribe.id, group = group.sequence, "dropped");
 
+						// The subscriber isn't listening any longer.
+						if complete {
+							continue;
+						}
+
 						let drop = message::GroupDrop {
 							sequence: group.sequence,
 							count: 0,
@@ -184,6 +332,18 @@ impl Publisher {
 				},
 				else => break,
 			}
+
+			// A bounded subscription is finished once every group has been served and the info has been sent.
+			if range.done && range.end.is_some() && tasks.is_empty() && active {
+				break;
+			}
+		}
+
+		// Tell the subscriber about any groups within the range that we never served.
+		if !complete {
+			for drop in range.missing() {
+				stream.writer.encode(&drop).await?;
+			}
 		}
 
 		tracing::info!("done");
@@ -191,29 +351,74 @@ impl Publisher {
 		Ok(())
 	}
 
-	#[tracing::instrument("group", skip_all, fields(?subscribe, ?priority, sequence = group.sequence))]
-	pub async fn serve_group(
-		mut session: web_transport::Session,
+	async fn send_info(stream: &mut Stream, info: &TrackInfo) -> Result<(), Error> {
+		let info = message::Info {
+			group_latest: info.group_latest,
+			group_order: info.order,
+			track_priority: info.priority,
+		};
+
+		tracing::info!(?info, "active");
+
+		stream.writer.encode(&info).await
+	}
+
+	#[tracing::instrument("group", skip_all, fields(?subscribe, sequence = group.sequence))]
+	async fn serve_group(
+		scheduler: Scheduler,
 		subscribe: u64,
-		priority: i32,
+		mut priority: watch::Receiver<Priority>,
+		mut cancel: watch::Receiver<Cancel>,
+		counters: Arc<Counters>,
 		group: &mut GroupConsumer,
 	) -> Result<(), Error> {
-		// TODO open streams in priority order to help with MAX_STREAMS flow control issues.
-		let mut stream = Writer::open(&mut session, message::DataType::Group).await?;
-		stream.set_priority(priority);
+		let sequence = group.sequence;
+
+		// Wait our turn to open a stream, so the most important groups get any MAX_STREAMS credit first.
+		// The priority is checked when credit is available, so it includes any updates while we wait.
+		let live = priority.clone();
+		let stream = tokio::select! {
+			res = scheduler.open(subscribe, move || live.borrow().stream(sequence)) => res?,
+			_ = Self::cancelled(&mut cancel, Cancel::pending) => return Err(Error::Cancel),
+		};
+
+		let mut stream = Writer::new(stream);
+		let _guard = counters.group();
+
+		priority.mark_changed();
+		Self::reprioritize(&mut stream, &mut priority, sequence);
 
 		tracing::trace!("serving");
 
-		Self::serve_group_inner(subscribe, group, &mut stream)
-			.await
-			.or_close(&mut stream)
+		let res = tokio::select! {
+			res = Self::serve_group_inner(subscribe, group, &mut stream, &mut priority, &counters) => res,
+			_ = Self::cancelled(&mut cancel, |cancel| cancel.contains(sequence)) => Err(Error::Cancel),
+		};
+
+		if res.is_err() {
+			counters.reset();
+		}
+
+		res.or_close(&mut stream)
 	}
 
-	pub async fn serve_group_inner(
+	// Resolves when the group should be reset because the subscription was cancelled.
+	async fn cancelled<F: FnMut(&Cancel) -> bool>(cancel: &mut watch::Receiver<Cancel>, reset: F) {
+		if cancel.wait_for(reset).await.is_err() {
+			// The subscription is gone, which also drops this task.
+			std::future::pending::<()>().await;
+		}
+	}
+
+	async fn serve_group_inner(
 		subscribe: u64,
 		group: &mut GroupConsumer,
 		stream: &mut Writer,
+		priority: &mut watch::Receiver<Priority>,
+		counters: &Counters,
 	) -> Result<(), Error> {
+		stream.encode(&message::DataType::Group).await?;
+
 		let msg = message::Group {
 			subscribe,
 			sequence: group.sequence,
@@ -224,16 +429,25 @@ impl Publisher {
 		let mut frames = 0;
 
 		while let Some(mut frame) = group.next_frame().await? {
+			Self::reprioritize(stream, priority, group.sequence);
+
 			let header = message::Frame { size: frame.size };
 			stream.encode(&header).await?;
 
 			let mut remain = frame.size;
 
-			while let Some(chunk) = frame.read().await? {
-				remain = remain.checked_sub(chunk.len()).ok_or(Error::WrongSize)?;
-				tracing::trace!(chunk = chunk.len(), remain, "chunk");
+			// Write every chunk received so far, so we only wake up once per batch.
+			// NOTE: The stream still copies each chunk into its send buffer.
+			while let Some(chunks) = frame.read_chunks().await? {
+				Self::reprioritize(stream, priority, group.sequence);
 
-				stream.write(&chunk).await?;
+				for chunk in chunks {
+					remain = remain.checked_sub(chunk.len()).ok_or(Error::WrongSize)?;
+					tracing::trace!(chunk = chunk.len(), remain, "chunk");
+
+					stream.write(&chunk).await?;
+					counters.bytes(chunk.len());
+				}
 			}
 
 			if remain > 0 {
@@ -241,6 +455,7 @@ impl Publisher {
 			}
 
 			frames += 1;
+			counters.frame();
 		}
 
 		tracing::debug!(frames, "served");
@@ -257,7 +472,7 @@ impl Publisher {
 	}
 
 	#[tracing::instrument("fetch", skip_all, err, fields(track = ?fetch.path, group = fetch.group, offset = fetch.offset))]
-	async fn serve_fetch(&mut self, _stream: &mut Stream, fetch: message::Fetch) -> Result<(), Error> {
+	async fn serve_fetch(&mut self, stream: &mut Stream, fetch: message::Fetch) -> Result<(), Error> {
 		let track = Track {
 			path: fetch.path,
 			priority: fetch.priority,
@@ -265,9 +480,74 @@ impl Publisher {
 		};
 
 		let track = self.get_track(track).await?;
-		let _group = track.get_group(fetch.group)?;
+		let mut group = track.get_group(fetch.group)?;
+
+		// Prioritize the fetch like it was a group within a subscription.
+		let priority = Self::stream_priority(track.priority, track.order, group.sequence);
+		stream.writer.set_priority(priority);
+
+		tracing::info!("serving");
+
+		let counters = self.stats.fetch(track.path.clone(), true);
+		let _guard = counters.group();
 
-		unimplemented!("TODO fetch");
+		let res = Self::serve_fetch_frames(&mut stream.writer, &mut group, fetch.offset, &counters).await;
+		if res.is_err() {
+			counters.reset();
+		}
+
+		res
+	}
+
+	async fn serve_fetch_frames(
+		stream: &mut Writer,
+		group: &mut GroupConsumer,
+		offset: usize,
+		counters: &Counters,
+	) -> Result<(), Error> {
+		// Skip any frames (or partial frames) before the offset.
+		let mut skip = offset;
+		let mut frames = 0;
+
+		while let Some(mut frame) = group.next_frame().await? {
+			if skip >= frame.size {
+				skip -= frame.size;
+				continue;
+			}
+
+			let header = message::Frame {
+				size: frame.size - skip,
+			};
+			stream.encode(&header).await?;
+
+			let mut remain = frame.size;
+
+			while let Some(mut chunk) = frame.read().await? {
+				remain = remain.checked_sub(chunk.len()).ok_or(Error::WrongSize)?;
+
+				if skip > 0 {
+					let size = skip.min(chunk.len());
+					let _ = chunk.split_to(size);
+					skip -= size;
+				}
+
+				if !chunk.is_empty() {
+					stream.write(&chunk).await?;
+					counters.bytes(chunk.len());
+				}
+			}
+
+			if remain > 0 {
+				return Err(Error::WrongSize);
+			}
+
+			frames += 1;
+			counters.frame();
+		}
+
+		tracing::info!(frames, "done");
+
+		Ok(())
 	}
 
 	pub async fn recv_info(&mut self, stream: &mut Stream) -> Result<(), Error> {
@@ -283,10 +563,11 @@ impl Publisher {
 		};
 		let track = self.get_track(track).await?;
 
+		let info = track.info().await?;
 		let info = message::Info {
-			group_latest: track.latest_group(),
-			track_priority: track.priority,
-			group_order: track.order,
+			group_latest: info.group_latest,
+			track_priority: info.priority,
+			group_order: info.order,
 		};
 
 		stream.writer.encode(&info).await?;
@@ -306,11 +587,22 @@ impl Publisher {
 		}
 	}
 
+	// Update the stream priority if it was changed by the subscriber.
+	// NOTE: This is only checked before writing each chunk.
+	fn reprioritize(stream: &mut Writer, priority: &mut watch::Receiver<Priority>, sequence: u64) {
+		if priority.has_changed().unwrap_or(false) {
+			let priority = priority.borrow_and_update().stream(sequence);
+
+			tracing::trace!(?priority, "priority");
+			stream.set_priority(priority);
+		}
+	}
+
 	// Quinn takes a i32 priority.
 	// We do our best to distill 70 bits of information into 32 bits, but overflows will happen.
 	// Specifically, group sequence 2^24 will overflow and be incorrectly prioritized.
 	// But even with a group per frame, it will take ~6 days to reach that point.
-	// TODO The behavior when two tracks share the same priority is undefined. Should we round-robin?
+	// See Fairness for how tracks with the same priority are scheduled.
 	fn stream_priority(track_priority: i8, group_order: GroupOrder, group_sequence: u64) -> i32 {
 		let sequence = (group_sequence as u32) & 0xFFFFFF;
 		(track_priority as i32) << 24
@@ -321,6 +613,169 @@ impl Publisher {
 	}
 }
 
+// Which groups in flight to reset once a subscription has been cancelled.
+// Groups still waiting for a stream are reset regardless.
+#[derive(Clone, Copy, Debug)]
+enum Cancel {
+	None,
+	Pending,
+	All,
+	Except(u64),
+}
+
+impl Cancel {
+	// Returns true if a group that's waiting for a stream should be reset.
+	fn pending(&self) -> bool {
+		!matches!(self, Self::None)
+	}
+
+	// Returns true if a group that's being served should be reset.
+	fn contains(&self, sequence: u64) -> bool {
+		match self {
+			Self::None | Self::Pending => false,
+			Self::All => true,
+			Self::Except(current) => sequence != *current,
+		}
+	}
+}
+
+// The inputs used to prioritize each group stream within a subscription.
+#[derive(Clone, Copy, Debug)]
+struct Priority {
+	track: i8,
+	order: GroupOrder,
+	fairness: Fairness,
+
+	// The oldest and newest groups currently being served.
+	oldest: u64,
+	newest: u64,
+}
+
+impl Priority {
+	// Returns true if the range of groups being served changed.
+	fn serving(&mut self, oldest: u64, newest: u64) -> bool {
+		let changed = (self.oldest, self.newest) != (oldest, newest);
+		self.oldest = oldest;
+		self.newest = newest;
+
+		// The rank of each group only matters for round-robin.
+		changed && self.fairness == Fairness::RoundRobin
+	}
+
+	fn stream(&self, sequence: u64) -> i32 {
+		match self.fairness {
+			Fairness::Sequence => Publisher::stream_priority(self.track, self.order, sequence),
+			Fairness::RoundRobin => {
+				// The most important group in each subscription has a rank of 0, so it ties with other tracks.
+				let rank = match self.order {
+					GroupOrder::Asc => sequence.saturating_sub(self.oldest),
+					GroupOrder::Desc => self.newest.saturating_sub(sequence),
+				};
+
+				Publisher::stream_priority(self.track, GroupOrder::Asc, rank.min(0xFFFFFF))
+			}
+		}
+	}
+}
+
+// Keeps track of which groups to serve for a subscription.
+struct Range {
+	start: u64,
+	end: Option<u64>,
+
+	// The largest sequence served so far.
+	latest: Option<u64>,
+
+	// The groups served within a bounded range, used to report any gaps.
+	served: BTreeSet<u64>,
+
+	// Set when there are no more groups to serve.
+	done: bool,
+
+	// Set when the track has ended.
+	closed: bool,
+}
+
+impl Range {
+	fn new(start: u64, end: Option<u64>) -> Self {
+		Self {
+			start,
+			end,
+			latest: None,
+			served: BTreeSet::new(),
+			done: false,
+			closed: false,
+		}
+	}
+
+	// Change the range, only affecting groups that have not been served yet.
+	fn update(&mut self, start: Option<u64>, end: Option<u64>) {
+		if let Some(start) = start {
+			self.start = start;
+		}
+
+		// Groups served while the range was unbounded are not tracked, so only report gaps after the latest.
+		if let (None, Some(latest)) = (self.end, self.latest) {
+			self.start = self.start.max(latest.saturating_add(1));
+		}
+
+		self.end = end;
+		self.done = self.closed || matches!((self.latest, end), (Some(latest), Some(end)) if latest >= end);
+	}
+
+	// The track has ended, so there are no more groups to serve.
+	fn close(&mut self) {
+		self.closed = true;
+		self.done = true;
+	}
+
+	// Returns true if the group should be served.
+	// Groups must be inserted in ascending order, otherwise they're skipped.
+	fn insert(&mut self, sequence: u64) -> bool {
+		if self.done || sequence < self.start || self.latest.is_some_and(|latest| sequence <= latest) {
+			return false;
+		}
+
+		if let Some(end) = self.end {
+			self.done = sequence >= end;
+
+			if sequence > end {
+				return false;
+			}
+
+			self.served.insert(sequence);
+		}
+
+		self.latest = Some(sequence);
+		true
+	}
+
+	// Returns a drop message for each run of groups within a bounded range that were not served.
+	fn missing(&self) -> Vec<message::GroupDrop> {
+		let end = match self.end {
+			Some(end) => end,
+			None => return Vec::new(),
+		};
+
+		let mut drops = Vec::new();
+		let mut sequence = self.start;
+
+		for next in self.served.range(self.start..).copied().chain([end.saturating_add(1)]) {
+			if next > sequence {
+				drops.push(message::GroupDrop {
+					sequence,
+					count: next - sequence - 1,
+					code: Error::NotFound.to_code(),
+				});
+			}
+
+			sequence = next.saturating_add(1);
+		}
+
+		drops
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -350,4 +805,67 @@ mod test {
 		assert(1, GroupOrder::Desc, 50, 2 * U24 - 49);
 		assert(1, GroupOrder::Desc, 0, 2 * U24 + 1);
 	}
+
+	#[test]
+	fn range_update() {
+		// A live subscription that serves a few groups before it's bounded.
+		let mut range = Range::new(5, None);
+		assert!(range.insert(5));
+		assert!(range.insert(7));
+		assert!(range.missing().is_empty());
+
+		range.update(None, Some(10));
+		assert!(range.insert(9));
+		range.close();
+
+		// Only the groups after the latest served group are reported.
+		let missing: Vec<_> = range.missing().iter().map(|drop| (drop.sequence, drop.count)).collect();
+		assert_eq!(missing, vec![(8, 0), (10, 0)]);
+
+		// Groups served before the new start of a bounded range are ignored.
+		let mut range = Range::new(5, Some(10));
+		assert!(range.insert(6));
+		range.update(Some(7), Some(10));
+		range.close();
+
+		let missing: Vec<_> = range.missing().iter().map(|drop| (drop.sequence, drop.count)).collect();
+		assert_eq!(missing, vec![(7, 3)]);
+	}
+
+	#[test]
+	fn round_robin() {
+		let priority = |fairness, order, oldest, newest| Priority {
+			track: 0,
+			order,
+			fairness,
+			oldest,
+			newest,
+		};
+
+		// Two tracks with the same priority but very different sequence numbers.
+		let a = priority(Fairness::RoundRobin, GroupOrder::Desc, 995, 1000);
+		let b = priority(Fairness::RoundRobin, GroupOrder::Desc, 3, 5);
+
+		// The newest group of each track ties, so they take turns.
+		assert_eq!(a.stream(1000), b.stream(5));
+		assert_eq!(a.stream(999), b.stream(4));
+
+		// Newer groups still win within a track.
+		assert!(a.stream(1000) < a.stream(999));
+
+		// The same for ascending order, based on the oldest group.
+		let a = priority(Fairness::RoundRobin, GroupOrder::Asc, 995, 1000);
+		let b = priority(Fairness::RoundRobin, GroupOrder::Asc, 3, 5);
+		assert_eq!(a.stream(995), b.stream(3));
+		assert!(a.stream(995) < a.stream(996));
+
+		// A higher priority track always wins.
+		let c = Priority { track: -1, ..b };
+		assert!(c.stream(5) < a.stream(995));
+
+		// Otherwise the track with the newest groups starves the other.
+		let a = priority(Fairness::Sequence, GroupOrder::Desc, 995, 1000);
+		let b = priority(Fairness::Sequence, GroupOrder::Desc, 3, 5);
+		assert!(a.stream(1000) < b.stream(5));
+	}
 }
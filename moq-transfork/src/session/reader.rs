@@ -2,7 +2,11 @@ use std::{cmp, fmt, io};
 
 use bytes::{Buf, Bytes, BytesMut};
 
-use crate::{coding::*, Error};
+use crate::{
+	coding::*,
+	message::{Version, Versioned},
+	Error,
+};
 
 use super::Close;
 
@@ -25,11 +29,23 @@ impl Reader {
 	}
 
 	pub async fn decode<T: Decode + fmt::Debug>(&mut self) -> Result<T, Error> {
+		self.decode_with(|r| T::decode(r)).await
+	}
+
+	// Decode a message with an encoding that depends on the negotiated version.
+	pub async fn decode_version<T: Versioned + fmt::Debug>(&mut self, version: Version) -> Result<T, Error> {
+		self.decode_with(|r| T::decode_version(r, version)).await
+	}
+
+	async fn decode_with<T, F>(&mut self, decode: F) -> Result<T, Error>
+	where
+		F: Fn(&mut io::Cursor<&BytesMut>) -> Result<T, DecodeError>,
+	{
 		loop {
 			let mut cursor = io::Cursor::new(&self.buffer);
 
 			// Try to decode with the current buffer.
-			match T::decode(&mut cursor) {
+			match decode(&mut cursor) {
 				Ok(msg) => {
 					self.buffer.advance(cursor.position() as usize);
 					return Ok(msg);
@@ -57,6 +73,18 @@ impl Reader {
 		}
 	}
 
+	// Decode optional messages at the end of a stream, with an encoding that depends on the negotiated version.
+	pub async fn decode_maybe_version<T: Versioned + fmt::Debug>(
+		&mut self,
+		version: Version,
+	) -> Result<Option<T>, Error> {
+		match self.finished().await {
+			Ok(()) => Ok(None),
+			Err(Error::Decode(DecodeError::ExpectedEnd)) => Ok(Some(self.decode_version(version).await?)),
+			Err(e) => Err(e),
+		}
+	}
+
 	// Returns a non-zero chunk of data, or None if the stream is closed
 	pub async fn read(&mut self, max: usize) -> Result<Option<Bytes>, Error> {
 		if !self.buffer.is_empty() {
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use tokio::sync::oneshot;
+
+use crate::Error;
+
+use moq_async::{spawn, Lock};
+
+// Opens unidirectional streams in priority order.
+//
+// The peer limits the number of concurrent streams via MAX_STREAMS.
+// When we're out of credit, every group would otherwise race to open a stream once credit frees up.
+// Instead, a single stream is opened at a time and handed to the highest priority request.
+// Requests with the same priority take turns based on their key (ex. the subscription), so one can't starve the others.
+#[derive(Clone)]
+pub(super) struct Scheduler {
+	session: web_transport::Session,
+	state: Lock<SchedulerState>,
+}
+
+impl Scheduler {
+	pub fn new(session: web_transport::Session) -> Self {
+		Self {
+			session,
+			state: Default::default(),
+		}
+	}
+
+	// Open a stream with the given priority, waiting behind any requests with a higher priority.
+	// The priority is evaluated each time a stream is available, so it can change while waiting.
+	// Ties go to the key that was given a stream least recently, then to the oldest request.
+	// NOTE: The lower the value, the higher the priority, matching Publisher::stream_priority.
+	pub async fn open<P>(&self, key: u64, priority: P) -> Result<web_transport::SendStream, Error>
+	where
+		P: Fn() -> i32 + Send + 'static,
+	{
+		let reply = {
+			let mut state = self.state.lock();
+
+			if let Some(stream) = state.spare.take() {
+				return Ok(stream);
+			}
+
+			let reply = state.push(key, priority);
+
+			if !state.opening {
+				state.opening = true;
+				spawn(self.clone().run());
+			}
+
+			reply
+		};
+
+		reply.await.map_err(|_| Error::Cancel)?
+	}
+
+	async fn run(mut self) {
+		loop {
+			// Wait until we have flow control credit, and only then decide who gets the stream.
+			let res = self.session.open_uni().await;
+
+			let mut state = self.state.lock();
+
+			match res {
+				Ok(stream) => {
+					if let Err(stream) = state.reply(stream) {
+						// Everybody gave up while we were waiting, so save it for the next request.
+						state.spare = Some(stream);
+					}
+				}
+				Err(err) => {
+					let err = Error::from(err);
+					for request in state.pending.drain(..) {
+						request.reply.send(Err(err.clone())).ok();
+					}
+				}
+			}
+
+			state.pending.retain(|request| !request.reply.is_closed());
+
+			// Forget the turns of any keys that are no longer waiting.
+			let SchedulerState { turns, pending, .. } = &mut *state;
+			turns.retain(|key, _| pending.iter().any(|request| request.key == *key));
+
+			if state.pending.is_empty() {
+				state.opening = false;
+				return;
+			}
+		}
+	}
+}
+
+#[derive(Default)]
+struct SchedulerState {
+	pending: Vec<Request>,
+
+	// Used to break ties, so requests with the same priority are served in order.
+	sequence: u64,
+
+	// The last turn each key was given a stream, so keys with the same priority take turns.
+	turns: HashMap<u64, u64>,
+	turn: u64,
+
+	// A stream that was opened after every request was cancelled.
+	spare: Option<web_transport::SendStream>,
+
+	// Set while a task is opening streams.
+	opening: bool,
+}
+
+impl SchedulerState {
+	fn push<P>(&mut self, key: u64, priority: P) -> oneshot::Receiver<Result<web_transport::SendStream, Error>>
+	where
+		P: Fn() -> i32 + Send + 'static,
+	{
+		let (reply, recv) = oneshot::channel();
+
+		self.pending.push(Request {
+			key,
+			priority: Box::new(priority),
+			sequence: self.sequence,
+			reply,
+		});
+		self.sequence += 1;
+
+		recv
+	}
+
+	// Remove the request with the lowest priority value.
+	// Ties go to the key that had a turn least recently (or never), then to the oldest request.
+	// NOTE: This is a linear scan because priorities can change at any time, which would corrupt a heap.
+	fn pop(&mut self) -> Option<Request> {
+		let index = self
+			.pending
+			.iter()
+			.enumerate()
+			.min_by_key(|(_, request)| ((request.priority)(), self.turns.get(&request.key), request.sequence))
+			.map(|(index, _)| index)?;
+
+		let request = self.pending.swap_remove(index);
+
+		self.turn += 1;
+		self.turns.insert(request.key, self.turn);
+
+		Some(request)
+	}
+
+	// Give the stream to the highest priority request that is still waiting, or return it if there are none.
+	fn reply(&mut self, stream: web_transport::SendStream) -> Result<(), web_transport::SendStream> {
+		let mut stream = stream;
+
+		while let Some(request) = self.pop() {
+			match request.reply.send(Ok(stream)) {
+				Ok(()) => return Ok(()),
+				Err(res) => stream = res.expect("sent a stream"),
+			}
+		}
+
+		Err(stream)
+	}
+}
+
+struct Request {
+	key: u64,
+	priority: Box<dyn Fn() -> i32 + Send>,
+	sequence: u64,
+	reply: oneshot::Sender<Result<web_transport::SendStream, Error>>,
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::{
+		atomic::{AtomicI32, Ordering},
+		Arc,
+	};
+
+	use super::*;
+
+	#[test]
+	fn order() {
+		let mut state = SchedulerState::default();
+
+		let _low = state.push(0, || 10);
+		let _high = state.push(1, || -5);
+		let _first = state.push(2, || 3);
+		let _second = state.push(3, || 3);
+
+		let order: Vec<_> = std::iter::from_fn(|| state.pop())
+			.map(|request| ((request.priority)(), request.sequence))
+			.collect();
+		assert_eq!(order, vec![(-5, 1), (3, 2), (3, 3), (10, 0)]);
+	}
+
+	#[test]
+	fn reprioritize() {
+		let mut state = SchedulerState::default();
+
+		let priority = Arc::new(AtomicI32::new(10));
+		let live = priority.clone();
+
+		let _changed = state.push(0, move || live.load(Ordering::Relaxed));
+		let _fixed = state.push(1, || 5);
+
+		// The priority is read when picking the next request, not when it was queued.
+		priority.store(0, Ordering::Relaxed);
+		assert_eq!(state.pop().unwrap().sequence, 0);
+		assert_eq!(state.pop().unwrap().sequence, 1);
+	}
+
+	#[test]
+	fn turns() {
+		let mut state = SchedulerState::default();
+
+		// The first key queues all of its requests before the second.
+		let _a = [state.push(0, || 3), state.push(0, || 3), state.push(0, || 3)];
+		let _b = [state.push(1, || 3), state.push(1, || 3)];
+
+		// Requests with the same priority alternate between keys instead of being served in order.
+		let order: Vec<_> = std::iter::from_fn(|| state.pop()).map(|request| request.key).collect();
+		assert_eq!(order, vec![0, 1, 0, 1, 0]);
+	}
+}
@@ -0,0 +1,409 @@
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Weak,
+	},
+};
+
+use web_time::Duration;
+
+use crate::Path;
+
+use moq_async::Lock;
+
+// The maximum number of tracks with totals, evicting the least recently updated.
+// Otherwise a long-lived session, like a relay, would remember every track it ever served.
+const MAX_TRACKS: usize = 1024;
+
+/// A snapshot of what a session is doing, returned by [super::Session::stats].
+#[derive(Clone, Debug, Default)]
+pub struct SessionStats {
+	/// The totals for each track, including subscriptions and fetches that have ended.
+	///
+	/// Only the most recently updated tracks are kept once there are too many.
+	pub tracks: HashMap<Path, TrackStats>,
+
+	/// The totals across every track, including any evicted from [Self::tracks].
+	pub total: TrackStats,
+
+	/// The active subscriptions we're serving to the peer.
+	pub publishing: Vec<SubscriptionStats>,
+
+	/// The active subscriptions we made to the peer.
+	pub subscribing: Vec<SubscriptionStats>,
+
+	/// The number of tracks we announced to the peer.
+	pub announces_sent: u64,
+
+	/// The number of tracks the peer announced to us.
+	pub announces_received: u64,
+
+	/// Statistics from the underlying transport, if supported.
+	pub transport: TransportStats,
+}
+
+/// The counters for a track or a single subscription.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackStats {
+	pub groups_sent: u64,
+	pub groups_received: u64,
+
+	pub frames_sent: u64,
+	pub frames_received: u64,
+
+	pub bytes_sent: u64,
+	pub bytes_received: u64,
+
+	/// The number of group streams reset, for example when a subscription was cancelled or the network failed.
+	pub resets_sent: u64,
+	pub resets_received: u64,
+
+	/// The number of group streams currently open.
+	pub streams: u64,
+}
+
+impl TrackStats {
+	fn add(&mut self, other: &TrackStats) {
+		self.groups_sent += other.groups_sent;
+		self.groups_received += other.groups_received;
+		self.frames_sent += other.frames_sent;
+		self.frames_received += other.frames_received;
+		self.bytes_sent += other.bytes_sent;
+		self.bytes_received += other.bytes_received;
+		self.resets_sent += other.resets_sent;
+		self.resets_received += other.resets_received;
+		self.streams += other.streams;
+	}
+}
+
+/// The counters for an active subscription.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriptionStats {
+	/// The subscribe ID, chosen by the subscriber.
+	pub id: u64,
+
+	/// The path of the track.
+	pub path: Path,
+
+	pub stats: TrackStats,
+}
+
+/// Statistics reported by the underlying transport, if supported.
+///
+/// These are not available via the generic WebTransport interface, so they're provided via [super::SessionBuilder::transport].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransportStats {
+	/// The estimated round trip time.
+	pub rtt: Option<Duration>,
+
+	/// The congestion window in bytes.
+	pub cwnd: Option<u64>,
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl From<&web_transport::quinn::Session> for TransportStats {
+	fn from(session: &web_transport::quinn::Session) -> Self {
+		Self {
+			rtt: Some(session.rtt()),
+			cwnd: Some(session.stats().path.cwnd),
+		}
+	}
+}
+
+// A callback used to query the transport, since the generic WebTransport session hides it.
+#[derive(Clone)]
+pub(super) struct Transport(Arc<dyn Fn() -> TransportStats + Send + Sync>);
+
+impl Transport {
+	pub fn new<F: Fn() -> TransportStats + Send + Sync + 'static>(f: F) -> Self {
+		Self(Arc::new(f))
+	}
+}
+
+impl fmt::Debug for Transport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Transport").finish()
+	}
+}
+
+// Collects the counters for a session, shared by the publisher and subscriber.
+#[derive(Clone, Default)]
+pub(super) struct Stats {
+	state: Lock<StatsState>,
+	transport: Option<Transport>,
+}
+
+#[derive(Default)]
+struct StatsState {
+	// The totals for subscriptions and fetches that have ended, with when they were last updated.
+	tracks: HashMap<Path, (u64, TrackStats)>,
+	updates: u64,
+
+	// The totals for any tracks evicted from the map above.
+	evicted: TrackStats,
+
+	// Weak so the counters are folded into the totals once every group is done.
+	publishing: Vec<(u64, Weak<Counters>)>,
+	subscribing: Vec<(u64, Weak<Counters>)>,
+
+	// Fetches are only reported as part of the track totals.
+	fetches: Vec<Weak<Counters>>,
+
+	announces_sent: u64,
+	announces_received: u64,
+}
+
+impl Stats {
+	pub fn new(transport: Option<Transport>) -> Self {
+		Self {
+			state: Default::default(),
+			transport,
+		}
+	}
+
+	// Start counting a subscription we're serving.
+	pub fn publishing(&self, id: u64, path: Path) -> Arc<Counters> {
+		self.register(id, path, true)
+	}
+
+	// Start counting a subscription we made.
+	pub fn subscribing(&self, id: u64, path: Path) -> Arc<Counters> {
+		self.register(id, path, false)
+	}
+
+	// Start counting a fetch, either one we're serving or one we made.
+	pub fn fetch(&self, path: Path, sending: bool) -> Arc<Counters> {
+		let counters = Arc::new(Counters::new(path, self.state.clone(), sending));
+
+		let mut state = self.state.lock();
+		state.fetches.retain(|counters| counters.strong_count() > 0);
+		state.fetches.push(Arc::downgrade(&counters));
+
+		counters
+	}
+
+	fn register(&self, id: u64, path: Path, sending: bool) -> Arc<Counters> {
+		let counters = Arc::new(Counters::new(path, self.state.clone(), sending));
+
+		let mut state = self.state.lock();
+		let active = match sending {
+			true => &mut state.publishing,
+			false => &mut state.subscribing,
+		};
+
+		active.retain(|(_, counters)| counters.strong_count() > 0);
+		active.push((id, Arc::downgrade(&counters)));
+
+		counters
+	}
+
+	pub fn announce_sent(&self) {
+		self.state.lock().announces_sent += 1;
+	}
+
+	pub fn announce_received(&self) {
+		self.state.lock().announces_received += 1;
+	}
+
+	pub fn snapshot(&self) -> SessionStats {
+		let upgrade = |list: &Vec<(u64, Weak<Counters>)>| -> Vec<(u64, Arc<Counters>)> {
+			list.iter()
+				.filter_map(|(id, counters)| Some((*id, counters.upgrade()?)))
+				.collect()
+		};
+
+		// NOTE: The upgraded counters must be dropped after the lock is released, as dropping them may need it.
+		let (mut stats, publishing, subscribing, fetches) = {
+			let state = self.state.lock();
+
+			let stats = SessionStats {
+				tracks: state
+					.tracks
+					.iter()
+					.map(|(path, (_, stats))| (path.clone(), stats.clone()))
+					.collect(),
+				total: state.evicted.clone(),
+				announces_sent: state.announces_sent,
+				announces_received: state.announces_received,
+				..Default::default()
+			};
+
+			let fetches: Vec<_> = state.fetches.iter().filter_map(Weak::upgrade).collect();
+
+			(stats, upgrade(&state.publishing), upgrade(&state.subscribing), fetches)
+		};
+
+		for (id, counters) in publishing {
+			stats.publishing.push(counters.snapshot(id));
+		}
+
+		for (id, counters) in subscribing {
+			stats.subscribing.push(counters.snapshot(id));
+		}
+
+		for active in stats.publishing.iter().chain(stats.subscribing.iter()) {
+			stats.tracks.entry(active.path.clone()).or_default().add(&active.stats);
+		}
+
+		for fetch in fetches {
+			stats.tracks.entry(fetch.path.clone()).or_default().add(&fetch.stats());
+		}
+
+		for track in stats.tracks.values() {
+			stats.total.add(track);
+		}
+
+		if let Some(transport) = &self.transport {
+			stats.transport = (transport.0)();
+		}
+
+		stats
+	}
+}
+
+impl StatsState {
+	// Add to the totals for a track, evicting the least recently updated track if there are too many.
+	fn total(&mut self, path: &Path, stats: &TrackStats) {
+		self.updates += 1;
+
+		let (updated, total) = self.tracks.entry(path.clone()).or_default();
+		*updated = self.updates;
+		total.add(stats);
+
+		if self.tracks.len() > MAX_TRACKS {
+			let oldest = self
+				.tracks
+				.iter()
+				.min_by_key(|(_, (updated, _))| *updated)
+				.map(|(path, _)| path.clone());
+
+			if let Some((_, stats)) = oldest.and_then(|oldest| self.tracks.remove(&oldest)) {
+				self.evicted.add(&stats);
+			}
+		}
+	}
+}
+
+// The counters for a single subscription or fetch, updated without a lock.
+pub(super) struct Counters {
+	path: Path,
+	state: Lock<StatsState>,
+
+	groups: AtomicU64,
+	frames: AtomicU64,
+	bytes: AtomicU64,
+	resets: AtomicU64,
+	streams: AtomicU64,
+
+	// Whether we're serving or made the subscription.
+	sending: bool,
+}
+
+impl Counters {
+	fn new(path: Path, state: Lock<StatsState>, sending: bool) -> Self {
+		Self {
+			path,
+			state,
+			groups: Default::default(),
+			frames: Default::default(),
+			bytes: Default::default(),
+			resets: Default::default(),
+			streams: Default::default(),
+			sending,
+		}
+	}
+
+	// A group stream was opened, returning a guard that tracks it until dropped.
+	pub fn group(self: &Arc<Self>) -> StreamGuard {
+		self.groups.fetch_add(1, Ordering::Relaxed);
+		self.streams.fetch_add(1, Ordering::Relaxed);
+
+		StreamGuard(self.clone())
+	}
+
+	pub fn frame(&self) {
+		self.frames.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn bytes(&self, size: usize) {
+		self.bytes.fetch_add(size as u64, Ordering::Relaxed);
+	}
+
+	pub fn reset(&self) {
+		self.resets.fetch_add(1, Ordering::Relaxed);
+	}
+
+	fn stats(&self) -> TrackStats {
+		let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+		if self.sending {
+			TrackStats {
+				groups_sent: load(&self.groups),
+				frames_sent: load(&self.frames),
+				bytes_sent: load(&self.bytes),
+				resets_sent: load(&self.resets),
+				streams: load(&self.streams),
+				..Default::default()
+			}
+		} else {
+			TrackStats {
+				groups_received: load(&self.groups),
+				frames_received: load(&self.frames),
+				bytes_received: load(&self.bytes),
+				resets_received: load(&self.resets),
+				streams: load(&self.streams),
+				..Default::default()
+			}
+		}
+	}
+
+	fn snapshot(&self, id: u64) -> SubscriptionStats {
+		SubscriptionStats {
+			id,
+			path: self.path.clone(),
+			stats: self.stats(),
+		}
+	}
+}
+
+impl Drop for Counters {
+	fn drop(&mut self) {
+		let stats = self.stats();
+		self.state.lock().total(&self.path, &stats);
+	}
+}
+
+// Decrements the number of open streams when dropped.
+pub(super) struct StreamGuard(Arc<Counters>);
+
+impl Drop for StreamGuard {
+	fn drop(&mut self) {
+		self.0.streams.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn max_tracks() {
+		let stats = Stats::default();
+
+		for i in 0..MAX_TRACKS + 10 {
+			let counters = stats.fetch(Path::default().push(i), true);
+			counters.frame();
+		}
+
+		// Only the most recent tracks are kept.
+		let snapshot = stats.snapshot();
+		assert_eq!(snapshot.tracks.len(), MAX_TRACKS);
+		assert!(!snapshot.tracks.contains_key(&Path::default().push(0)));
+		assert_eq!(snapshot.tracks[&Path::default().push(MAX_TRACKS + 9)].frames_sent, 1);
+
+		// But they're still included in the session totals.
+		assert_eq!(snapshot.total.frames_sent, MAX_TRACKS as u64 + 10);
+	}
+}
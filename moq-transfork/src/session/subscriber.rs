@@ -1,31 +1,51 @@
 use std::{
-	collections::{hash_map, HashMap},
+	collections::{hash_map, BTreeMap, HashMap},
+	mem,
+	pin::pin,
 	sync::{atomic, Arc},
+	task::Poll,
 };
 
+use tokio::sync::mpsc;
+
 use crate::{
 	message,
-	model::{Track, TrackConsumer},
-	AnnouncedProducer, Error, Path, TrackProducer,
+	model::{Group, GroupConsumer, GroupProducer, Track, TrackConsumer, TrackInfo, TrackRequest},
+	AnnouncedFilter, AnnouncedProducer, Error, Path, TrackProducer,
 };
 
 use moq_async::{spawn, Lock, OrClose};
 
-use super::{AnnouncedConsumer, Reader, Stream};
+use super::{AnnouncedConsumer, Counters, Reader, Stats, Stream};
 
 #[derive(Clone)]
 pub(super) struct Subscriber {
 	session: web_transport::Session,
+	version: message::Version,
+	stats: Stats,
 
 	tracks: Lock<HashMap<Path, TrackProducer>>,
-	subscribes: Lock<HashMap<u64, TrackProducer>>,
+	subscribes: Lock<HashMap<u64, Subscription>>,
 	next_id: Arc<atomic::AtomicU64>,
 }
 
+// An active subscription, shared with each incoming group stream.
+#[derive(Clone)]
+struct Subscription {
+	track: TrackProducer,
+
+	// Notified with the sequence number of each group once it's been fully received or failed.
+	received: mpsc::UnboundedSender<u64>,
+
+	counters: Arc<Counters>,
+}
+
 impl Subscriber {
-	pub fn new(session: web_transport::Session) -> Self {
+	pub fn new(session: web_transport::Session, version: message::Version, stats: Stats) -> Self {
 		Self {
 			session,
+			version,
+			stats,
 
 			tracks: Default::default(),
 			subscribes: Default::default(),
@@ -33,12 +53,17 @@ impl Subscriber {
 		}
 	}
 
-	/// Discover any tracks matching a prefix.
-	pub fn announced(&self, prefix: Path) -> AnnouncedConsumer {
+	/// Discover any tracks matching a filter.
+	pub fn announced(&self, filter: AnnouncedFilter) -> AnnouncedConsumer {
 		let producer = AnnouncedProducer::default();
-		let consumer = producer.subscribe_prefix(prefix.clone());
+
+		// NOTE: Older versions can't filter, so we filter locally too.
+		let consumer = producer.subscribe_filter(filter.clone());
 
 		let mut session = self.session.clone();
+		let stats = self.stats.clone();
+		let version = self.version;
+
 		spawn(async move {
 			let mut stream = match Stream::open(&mut session, message::ControlType::Announce).await {
 				Ok(stream) => stream,
@@ -48,7 +73,7 @@ impl Subscriber {
 				}
 			};
 
-			if let Err(err) = Self::run_announce(&mut stream, prefix, producer)
+			if let Err(err) = Self::run_announce(&mut stream, filter, producer, stats, version)
 				.await
 				.or_close(&mut stream)
 			{
@@ -59,20 +84,36 @@ impl Subscriber {
 		consumer
 	}
 
-	async fn run_announce(stream: &mut Stream, prefix: Path, mut announced: AnnouncedProducer) -> Result<(), Error> {
-		stream
-			.writer
-			.encode(&message::AnnouncePlease { prefix: prefix.clone() })
-			.await?;
+	async fn run_announce(
+		stream: &mut Stream,
+		filter: AnnouncedFilter,
+		mut announced: AnnouncedProducer,
+		stats: Stats,
+		version: message::Version,
+	) -> Result<(), Error> {
+		let please = message::AnnouncePlease {
+			prefix: filter.prefix.clone(),
+			pattern: filter.pattern,
+			depth: filter.depth.map(|depth| depth as u64),
+		};
+		stream.writer.encode_version(&please, version).await?;
+
+		let prefix = filter.prefix;
 
 		tracing::debug!(?prefix, "waiting for announcements");
 
 		loop {
 			tokio::select! {
-				res = stream.reader.decode_maybe::<message::Announce>() => {
+				res = stream.reader.decode_maybe_version::<message::Announce>(version) => {
 					match res? {
 						// Handle the announce
-						Some(announce) => Self::recv_announce(announce, &prefix, &mut announced)?,
+						Some(announce) => {
+							if let message::Announce::Active { .. } = announce {
+								stats.announce_received();
+							}
+
+							Self::recv_announce(announce, &prefix, &mut announced)?
+						},
 						// Stop if the stream has been closed
 						None => return Ok(()),
 					}
@@ -89,10 +130,10 @@ impl Subscriber {
 		announced: &mut AnnouncedProducer,
 	) -> Result<(), Error> {
 		match announce {
-			message::Announce::Active { suffix } => {
+			message::Announce::Active { suffix, metadata } => {
 				let path = prefix.clone().append(&suffix);
 				tracing::debug!(?path, "active");
-				if !announced.announce(path) {
+				if !announced.announce_with(path, metadata) {
 					return Err(Error::Duplicate);
 				}
 			}
@@ -111,37 +152,86 @@ impl Subscriber {
 		Ok(())
 	}
 
-	/// Subscribe to a given track.
-	pub fn subscribe(&self, track: Track) -> TrackConsumer {
+	/// Subscribe to the requested groups of a given track.
+	pub fn subscribe(&self, track: Track, request: TrackRequest) -> TrackConsumer {
 		let path = track.path.clone();
-		let (writer, reader) = track.clone().produce();
+		let (mut writer, reader) = track.clone().produce();
+
+		// The publisher decides the track info, so wait for the response.
+		writer.clear_info();
+
+		// Only deduplicate subscriptions for the live track, not a specific range.
+		let ranged = request.is_ranged();
 
 		// Check if we can deduplicate this subscription
-		match self.tracks.lock().entry(path.clone()) {
-			hash_map::Entry::Occupied(entry) => return entry.get().subscribe(),
-			hash_map::Entry::Vacant(entry) => entry.insert(writer.clone()),
-		};
+		if !ranged {
+			match self.tracks.lock().entry(path.clone()) {
+				hash_map::Entry::Occupied(entry) => return entry.get().subscribe(),
+				hash_map::Entry::Vacant(entry) => entry.insert(writer.clone()),
+			};
+		}
 
 		let mut this = self.clone();
 		let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
 
 		spawn(async move {
-			if let Ok(mut stream) = Stream::open(&mut this.session, message::ControlType::Subscribe).await {
-				if let Err(err) = this.run_subscribe(id, writer, &mut stream).await.or_close(&mut stream) {
-					tracing::warn!(?err, "subscribe error");
-				}
+			let res = match Stream::open(&mut this.session, message::ControlType::Subscribe).await {
+				Ok(mut stream) => this
+					.run_subscribe(id, writer.clone(), request, &mut stream)
+					.await
+					.or_close(&mut stream),
+				Err(err) => Err(err),
+			};
+
+			if let Err(err) = res {
+				tracing::warn!(?err, "subscribe error");
+				writer.close(err);
 			}
 
 			this.subscribes.lock().remove(&id);
-			this.tracks.lock().remove(&path);
+
+			if !ranged {
+				this.tracks.lock().remove(&path);
+			}
 		});
 
 		reader
 	}
 
+	/// Subscribe to the live track on behalf of an existing producer, returning once the subscription ends.
+	pub async fn subscribe_producer(&self, track: TrackProducer) -> Result<(), Error> {
+		let mut this = self.clone();
+		let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
+
+		let res = match Stream::open(&mut this.session, message::ControlType::Subscribe).await {
+			Ok(mut stream) => this
+				.run_subscribe(id, track, TrackRequest::default(), &mut stream)
+				.await
+				.or_close(&mut stream),
+			Err(err) => Err(err),
+		};
+
+		this.subscribes.lock().remove(&id);
+
+		res
+	}
+
 	#[tracing::instrument("subscribe", skip_all, fields(?id, track = ?track.path))]
-	async fn run_subscribe(&mut self, id: u64, track: TrackProducer, stream: &mut Stream) -> Result<(), Error> {
-		self.subscribes.lock().insert(id, track.clone());
+	async fn run_subscribe(
+		&mut self,
+		id: u64,
+		mut track: TrackProducer,
+		request: TrackRequest,
+		stream: &mut Stream,
+	) -> Result<(), Error> {
+		let (received, mut groups) = mpsc::unbounded_channel();
+
+		let subscription = Subscription {
+			track: track.clone(),
+			received,
+			counters: self.stats.subscribing(id, track.path.clone()),
+		};
+		self.subscribes.lock().insert(id, subscription);
 
 		let request = message::Subscribe {
 			id,
@@ -149,31 +239,90 @@ impl Subscriber {
 			priority: track.priority,
 
 			group_order: track.order,
+			group_min: request.group_min,
+			group_max: request.group_max,
 
-			// TODO
-			group_min: None,
-			group_max: None,
+			drain: request.drain,
 		};
 
-		stream.writer.encode(&request).await?;
+		stream.writer.encode_version(&request, self.version).await?;
 
-		// TODO use the response to correctly populate the track info
 		let info: message::Info = stream.reader.decode().await?;
 
 		tracing::info!(?info, "active");
 
-		loop {
+		track.set_info(TrackInfo {
+			priority: info.track_priority,
+			order: info.group_order,
+			group_latest: info.group_latest,
+		});
+
+		// A bounded subscription is done once every group in the range has been received or dropped.
+		// The publisher starts at the latest group unless a minimum was requested.
+		let mut start = request.group_min.unwrap_or(info.group_latest);
+		let mut remaining = request.group_max.map(|end| Remaining::new(start, end));
+		let mut complete = false;
+
+		// The largest sequence received so far.
+		let mut latest = None;
+
+		// A separate handle so we can wait for updates and unused at the same time.
+		let mut updates = track.clone();
+
+		while !remaining.as_ref().is_some_and(Remaining::is_empty) {
 			tokio::select! {
-				res = stream.reader.decode_maybe::<message::GroupDrop>() => {
+				res = stream.reader.decode_maybe::<message::GroupDrop>(), if !complete => {
 					match res? {
 						Some(drop) => {
 							tracing::info!(?drop, "dropped");
-							// TODO expose updates to application
-							// TODO use to detect gaps
+
+							if let Some(remaining) = remaining.as_mut() {
+								remaining.remove(drop.sequence, drop.count);
+							}
+
+							track.drop_groups(drop.sequence, drop.sequence.saturating_add(drop.count));
 						},
+						// Keep waiting for any group streams still in flight.
+						None if remaining.is_some() => complete = true,
 						None => break,
 					}
 				}
+				Some(sequence) = groups.recv() => {
+					latest = latest.max(Some(sequence));
+
+					if let Some(remaining) = remaining.as_mut() {
+						remaining.remove(sequence, 0);
+					}
+				}
+				update = updates.updated() => {
+					tracing::info!(?update, "updating");
+
+					let msg = message::SubscribeUpdate {
+						priority: update.priority,
+						group_order: update.order,
+						group_min: update.group_min,
+						group_max: update.group_max,
+					};
+					stream.writer.encode_version(&msg, self.version).await?;
+
+					start = update.group_min.unwrap_or(start);
+					remaining = match (remaining.take(), update.group_max) {
+						(Some(mut remaining), Some(end)) => {
+							remaining.update(start, end);
+							Some(remaining)
+						}
+						// Like the publisher, only wait for groups after the latest one received while unbounded.
+						(None, Some(end)) => {
+							if let Some(latest) = latest {
+								start = start.max(latest.saturating_add(1));
+							}
+
+							Some(Remaining::new(start, end))
+						}
+						(_, None) if complete => break,
+						(_, None) => None,
+					};
+				}
 				// Close when there are no more subscribers
 				_ = track.unused() => break
 			};
@@ -191,27 +340,244 @@ impl Subscriber {
 
 	#[tracing::instrument("group", skip_all, err, fields(subscribe = ?group.subscribe, group = group.sequence))]
 	pub async fn recv_group_inner(&mut self, stream: &mut Reader, group: message::Group) -> Result<(), Error> {
-		let mut group = {
+		let (mut track, mut group, counters, received) = {
 			let mut subs = self.subscribes.lock();
-			let track = subs.get_mut(&group.subscribe).ok_or(Error::Cancel)?;
+			let subscription = subs.get_mut(&group.subscribe).ok_or(Error::Cancel)?;
+
+			let group = subscription.track.create_group(group.sequence);
+
+			(
+				subscription.track.clone(),
+				group,
+				subscription.counters.clone(),
+				subscription.received.clone(),
+			)
+		};
+
+		let _guard = counters.group();
+		let res = Self::recv_frames(stream, &mut group, &counters).await;
+
+		// Only count the group once it's done, otherwise a bounded subscription would be cancelled while it's still being read.
+		received.send(group.sequence).ok();
+
+		if let Err(err) = res {
+			counters.reset();
+			track.reset_group(group.sequence, err.to_code());
+			group.close(err.clone());
+			return Err(err);
+		}
+
+		Ok(())
+	}
+
+	/// Request the info for a track without subscribing to it.
+	#[tracing::instrument("info", skip_all, err, fields(?path))]
+	pub async fn info(&self, path: Path) -> Result<TrackInfo, Error> {
+		let mut session = self.session.clone();
+		let mut stream = Stream::open(&mut session, message::ControlType::Info).await?;
+
+		Self::run_info(&mut stream, path).await.or_close(&mut stream)
+	}
 
-			track.create_group(group.sequence)
+	async fn run_info(stream: &mut Stream, path: Path) -> Result<TrackInfo, Error> {
+		stream.writer.encode(&message::InfoRequest { path }).await?;
+		let info: message::Info = stream.reader.decode().await?;
+
+		Ok(TrackInfo {
+			priority: info.track_priority,
+			order: info.group_order,
+			group_latest: info.group_latest,
+		})
+	}
+
+	/// Subscribe to a single group, starting at the given byte offset.
+	pub fn fetch(&self, track: Track, sequence: u64, offset: usize) -> GroupConsumer {
+		let (writer, reader) = Group::new(sequence).produce();
+
+		let mut session = self.session.clone();
+		let stats = self.stats.clone();
+
+		spawn(async move {
+			let mut stream = match Stream::open(&mut session, message::ControlType::Fetch).await {
+				Ok(stream) => stream,
+				Err(err) => {
+					tracing::warn!(?err, "failed to open fetch stream");
+					writer.close(err);
+					return;
+				}
+			};
+
+			if let Err(err) = Self::run_fetch(&mut stream, track, offset, writer.clone(), stats)
+				.await
+				.or_close(&mut stream)
+			{
+				tracing::warn!(?err, "fetch error");
+				writer.close(err);
+			}
+		});
+
+		reader
+	}
+
+	#[tracing::instrument("fetch", skip_all, err, fields(track = ?track.path, group = group.sequence, offset))]
+	async fn run_fetch(
+		stream: &mut Stream,
+		track: Track,
+		offset: usize,
+		mut group: GroupProducer,
+		stats: Stats,
+	) -> Result<(), Error> {
+		let counters = stats.fetch(track.path.clone(), false);
+
+		let request = message::Fetch {
+			path: track.path,
+			priority: track.priority,
+			group: group.sequence,
+			offset,
 		};
 
+		stream.writer.encode(&request).await?;
+
+		let _guard = counters.group();
+		if let Err(err) = Self::recv_frames(&mut stream.reader, &mut group, &counters).await {
+			counters.reset();
+			return Err(err);
+		}
+
+		tracing::info!(frames = group.frame_count(), "done");
+
+		Ok(())
+	}
+
+	async fn recv_frames(stream: &mut Reader, group: &mut GroupProducer, counters: &Counters) -> Result<(), Error> {
 		while let Some(frame) = stream.decode_maybe::<message::Frame>().await? {
-			let mut frame = group.create_frame(frame.size);
+			counters.frame();
+
+			// Stop reading if the group exceeds the memory limits.
+			let mut frame = group.try_create_frame(frame.size)?;
 			let mut remain = frame.size;
 
+			let mut chunks = Vec::new();
+
 			while remain > 0 {
-				let chunk = stream.read(remain).await?.ok_or(Error::WrongSize)?;
+				// Grab any chunks that are already available, only waking consumers once we would block.
+				// The read is awaited instead of dropped when pending, as dropping it could lose data on some backends.
+				let mut read = pin!(stream.read(remain));
+				let res = match futures::poll!(read.as_mut()) {
+					Poll::Ready(res) => res,
+					Poll::Pending => {
+						frame.write_chunks(mem::take(&mut chunks));
+						read.await
+					}
+				};
+
+				let chunk = res?.ok_or(Error::WrongSize)?;
 
 				remain = remain.checked_sub(chunk.len()).ok_or(Error::WrongSize)?;
 				tracing::trace!(size = chunk.len(), remain, "chunk");
 
-				frame.write(chunk);
+				counters.bytes(chunk.len());
+
+				chunks.push(chunk);
 			}
+
+			frame.write_chunks(chunks);
 		}
 
 		Ok(())
 	}
 }
+
+// The groups within a bounded subscription that have not been received or dropped yet.
+struct Remaining {
+	start: u64,
+	end: u64,
+
+	// Runs of groups that are done, keyed by the first sequence and merged when adjacent.
+	// A drop can cover a huge number of groups, so we can't store them individually.
+	done: BTreeMap<u64, u64>,
+}
+
+impl Remaining {
+	fn new(start: u64, end: u64) -> Self {
+		Self {
+			start,
+			end,
+			done: BTreeMap::new(),
+		}
+	}
+
+	// Mark the sequence and the following `count` groups as done.
+	fn remove(&mut self, sequence: u64, count: u64) {
+		let mut first = sequence.max(self.start);
+		let mut last = sequence.saturating_add(count).min(self.end);
+
+		if first > last {
+			return;
+		}
+
+		// Merge with any runs that overlap or are adjacent.
+		let merged: Vec<(u64, u64)> = self
+			.done
+			.range(..=last.saturating_add(1))
+			.rev()
+			.take_while(|(_, &end)| end.saturating_add(1) >= first)
+			.map(|(&start, &end)| (start, end))
+			.collect();
+
+		for (start, end) in merged {
+			self.done.remove(&start);
+			first = first.min(start);
+			last = last.max(end);
+		}
+
+		self.done.insert(first, last);
+	}
+
+	// Change the range, forgetting about any groups outside of it.
+	fn update(&mut self, start: u64, end: u64) {
+		self.start = start;
+		self.end = end;
+		self.done = std::mem::take(&mut self.done)
+			.into_iter()
+			.map(|(first, last)| (first.max(start), last.min(end)))
+			.filter(|(first, last)| first <= last)
+			.collect();
+	}
+
+	fn is_empty(&self) -> bool {
+		self.start > self.end || self.done.get(&self.start) == Some(&self.end)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn remaining() {
+		let mut remaining = Remaining::new(1, 10);
+		remaining.remove(1, 0);
+		remaining.remove(3, 2);
+		assert!(!remaining.is_empty());
+
+		// Fill in the gap, merging the runs.
+		remaining.remove(2, 0);
+		remaining.remove(8, 0);
+		assert_eq!(remaining.done.len(), 2);
+
+		remaining.remove(6, u64::MAX);
+		assert!(remaining.is_empty());
+
+		// Extending the range adds new groups.
+		remaining.update(5, 20);
+		assert!(!remaining.is_empty());
+		remaining.remove(11, 9);
+		assert!(remaining.is_empty());
+
+		// A huge drop is handled without iterating over each group.
+		let mut remaining = Remaining::new(0, u64::MAX);
+		remaining.remove(0, u64::MAX);
+		assert!(remaining.is_empty());
+	}
+}
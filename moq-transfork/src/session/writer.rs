@@ -1,6 +1,10 @@
 use std::fmt;
 
-use crate::{coding::*, message, Error};
+use crate::{
+	coding::*,
+	message::{Version, Versioned},
+	Error,
+};
 
 use moq_async::Close;
 
@@ -17,19 +21,20 @@ impl Writer {
 		}
 	}
 
-	pub async fn open(session: &mut web_transport::Session, typ: message::DataType) -> Result<Self, Error> {
-		let send = session.open_uni().await?;
-
-		let mut writer = Self::new(send);
-		writer.encode(&typ).await?;
-
-		Ok(writer)
-	}
-
 	pub async fn encode<T: Encode + fmt::Debug>(&mut self, msg: &T) -> Result<(), Error> {
 		self.buffer.clear();
 		msg.encode(&mut self.buffer);
+		self.flush().await
+	}
+
+	// Encode a message with an encoding that depends on the negotiated version.
+	pub async fn encode_version<T: Versioned + fmt::Debug>(&mut self, msg: &T, version: Version) -> Result<(), Error> {
+		self.buffer.clear();
+		msg.encode_version(&mut self.buffer, version);
+		self.flush().await
+	}
 
+	async fn flush(&mut self) -> Result<(), Error> {
 		while !self.buffer.is_empty() {
 			self.stream.write_buf(&mut self.buffer).await?;
 		}
@@ -1,7 +1,7 @@
 use std::collections::{hash_map::Entry, HashMap};
 
 use baton::Baton;
-use moq_karp::moq_transfork::{Announced, AnnouncedConsumer, AnnouncedProducer, Path};
+use moq_karp::moq_transfork::{Announced, AnnouncedConsumer, AnnouncedFilter, AnnouncedProducer, Path};
 use url::Url;
 use wasm_bindgen_futures::spawn_local;
 
@@ -68,14 +68,17 @@ impl Backend {
 					let session = session?;
 					self.producer.reset();
 					let path = self.connect.take().unwrap().path;
-					self.announced = Some(session.announced(path));
+
+					// Only discover each participant's broadcast, not every track within it.
+					let filter = AnnouncedFilter::new(path.push("*").push("*")).depth(2);
+					self.announced = Some(session.announced_filter(filter));
 					self.status.connection.update(ConnectionStatus::Connected);
 				},
 				Some(announce) = async { Some(self.announced.as_mut()?.next().await) } => {
 					tracing::info!(?announce, "iannounce");
 					let announce = announce.ok_or(Error::Closed)?;
 					match announce {
-						Announced::Active(suffix) => self.announced(suffix),
+						Announced::Active(suffix, _) => self.announced(suffix),
 						Announced::Ended(suffix) => self.unannounced(suffix),
 						Announced::Live => self.live(),
 					}
@@ -86,11 +89,6 @@ impl Backend {
 	}
 
 	fn announced(&mut self, suffix: Path) {
-		// TODO only announce a single level deep
-		if suffix.len() != 2 {
-			return;
-		}
-
 		// Annoying that we have to do this.
 		let name = suffix.first().cloned().unwrap();
 		match self.unique.entry(name.clone()) {
@@ -107,11 +105,6 @@ impl Backend {
 	}
 
 	fn unannounced(&mut self, suffix: Path) {
-		// TODO only announce a single level deep
-		if suffix.len() != 2 {
-			return;
-		}
-
 		// Annoying that we have to do this.
 		let name = suffix.first().unwrap();
 		if let Entry::Occupied(mut entry) = self.unique.entry(name.clone()) {
@@ -87,7 +87,7 @@ impl MeetAnnounced {
 		let next = self.inner.next().await?;
 		tracing::info!(?next);
 		Some(match next {
-			Announced::Active(suffix) => MeetAnnounce {
+			Announced::Active(suffix, _) => MeetAnnounce {
 				action: MeetAction::Join,
 				name: suffix[0].clone(),
 			},